@@ -0,0 +1,90 @@
+use actix_web::{http::header, HttpRequest};
+
+use crate::configuration::Secret;
+use crate::error::ApiError;
+
+/// The header in which Tecken's upload clients send their token.
+pub const AUTH_TOKEN_HEADER: &str = "Auth-Token";
+
+/// A set of tokens which grant access to a group of endpoints.
+#[derive(Debug, Clone)]
+pub struct AccessTokens {
+    tokens: Vec<String>,
+}
+
+impl AccessTokens {
+    /// Reads the tokens from their environment variables or files. Fails if
+    /// one can't be read or is empty.
+    pub fn read(secrets: &[Secret]) -> Result<Self, String> {
+        let tokens = secrets
+            .iter()
+            .map(|secret| match secret.read()? {
+                token if token.is_empty() => Err(format!("The token in {secret:?} is empty")),
+                token => Ok(token),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { tokens })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Checks the token in the `Auth-Token` header, or in the
+    /// `Authorization: Bearer <token>` header.
+    pub fn check(&self, req: &HttpRequest) -> Result<(), ApiError> {
+        let Some(token) = request_token(req) else {
+            return Err(ApiError::Unauthorized(format!(
+                "Missing {AUTH_TOKEN_HEADER} or Authorization header"
+            )));
+        };
+        let is_valid = self
+            .tokens
+            .iter()
+            .any(|t| constant_time_eq(t.as_bytes(), token.as_bytes()));
+        if !is_valid {
+            return Err(ApiError::Forbidden("Invalid token".into()));
+        }
+        Ok(())
+    }
+}
+
+/// The tokens for the `/admin/` endpoints, from [`AdminSettings`].
+///
+/// [`AdminSettings`]: crate::configuration::AdminSettings
+pub struct AdminTokens(pub AccessTokens);
+
+fn request_token(req: &HttpRequest) -> Option<&str> {
+    if let Some(token) = req.headers().get(AUTH_TOKEN_HEADER) {
+        return token.to_str().ok();
+    }
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_tokens() {
+        let dir = std::env::temp_dir().join("reliost-test-auth-tokens");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("token"), "secret-token\n").unwrap();
+        std::fs::write(dir.join("empty"), "\n").unwrap();
+
+        let tokens = AccessTokens::read(&[Secret::File(dir.join("token"))]).unwrap();
+        assert_eq!(tokens.tokens, vec!["secret-token".to_owned()]);
+        assert!(AccessTokens::read(&[Secret::File(dir.join("empty"))])
+            .unwrap_err()
+            .contains("empty"));
+        assert!(AccessTokens::read(&[Secret::File(dir.join("missing"))]).is_err());
+    }
+}
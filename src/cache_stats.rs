@@ -0,0 +1,317 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::configuration::Settings;
+use crate::pins::Pins;
+use crate::quota::absolute_path;
+use crate::quota_db::{unix_seconds, InventoryFile, QuotaDatabase};
+
+/// How often we compare the inventory with the previous one to detect
+/// evictions.
+pub const EVICTION_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How many files are listed in each of the oldest, newest and largest lists.
+const LISTED_FILE_COUNT: usize = 10;
+
+/// How many evictions we remember.
+const MAX_RECENT_EVICTIONS: usize = 100;
+
+/// Statistics about the quota managed caches, from the quota managers'
+/// databases.
+pub struct CacheStats {
+    /// The managed directories of `[quota]` and `[[quota.caches]]`.
+    quotas: Vec<ManagedDir>,
+    /// The cache directories inside the managed directories, by name.
+    subdirs: Vec<(String, PathBuf)>,
+    pins: Arc<Pins>,
+    evictions: Mutex<EvictionTracker>,
+}
+
+struct ManagedDir {
+    dir: PathBuf,
+    db_path: PathBuf,
+    size_limit: Option<u64>,
+    age_limit: Option<Duration>,
+}
+
+/// samply-quota-manager doesn't record the files it evicts, so we detect
+/// them by comparing the inventory with the one we saw last time. Files which
+/// left the inventory and no longer exist were evicted.
+#[derive(Default)]
+struct EvictionTracker {
+    known_files: Option<HashMap<PathBuf, u64>>,
+    recent: VecDeque<(PathBuf, u64, Instant)>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStatsResponse {
+    total_size: u64,
+    file_count: usize,
+    /// Each quota manager, with its limits and its usage.
+    quotas: Vec<QuotaStats>,
+    /// The cached files of pinned libraries, which are included above.
+    pinned: PinnedStats,
+    subdirectories: Vec<SubdirectoryStats>,
+    /// The least recently used files, which are evicted first.
+    oldest_files: Vec<FileStats>,
+    newest_files: Vec<FileStats>,
+    largest_files: Vec<FileStats>,
+    recent_evictions: Vec<EvictionStats>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaStats {
+    managed_dir: PathBuf,
+    size_limit: Option<u64>,
+    age_limit_seconds: Option<u64>,
+    total_size: u64,
+    file_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinnedStats {
+    total_size: u64,
+    file_count: usize,
+    largest_files: Vec<FileStats>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubdirectoryStats {
+    name: String,
+    dir: PathBuf,
+    total_size: u64,
+    file_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileStats {
+    path: PathBuf,
+    size: u64,
+    /// Seconds since the Unix epoch.
+    last_access: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvictionStats {
+    path: PathBuf,
+    size: u64,
+    /// Evictions are only noticed every [`EVICTION_POLL_INTERVAL`], so this
+    /// is when we noticed it.
+    detected_seconds_ago: u64,
+}
+
+impl FileStats {
+    fn new(file: &InventoryFile) -> Self {
+        Self {
+            path: file.path.clone(),
+            size: file.size,
+            last_access: file.last_access.map(unix_seconds),
+        }
+    }
+}
+
+impl CacheStats {
+    /// Returns `None` if there's no quota manager.
+    pub fn new(settings: &Settings, pins: Arc<Pins>) -> Option<Self> {
+        let quota = settings.quota.as_ref()?;
+        let mut quotas = vec![ManagedDir {
+            dir: absolute_path(&quota.managed_dir),
+            db_path: quota.db_path.clone(),
+            size_limit: quota.size_limit,
+            age_limit: quota.age_limit,
+        }];
+        quotas.extend(quota.caches.iter().map(|cache| ManagedDir {
+            dir: absolute_path(&cache.dir),
+            db_path: cache.db_path.clone(),
+            size_limit: cache.size_limit,
+            age_limit: cache.age_limit,
+        }));
+        let mut subdirs = Vec::new();
+        if let Some(symbols) = &settings.symbols {
+            if let Some(breakpad) = &symbols.breakpad {
+                subdirs.push(("breakpad".to_owned(), breakpad.cache_dir.clone()));
+                if let Some(symindex_dir) = &breakpad.symindex_dir {
+                    subdirs.push(("symindex".to_owned(), symindex_dir.clone()));
+                }
+            }
+            if let Some(windows) = &symbols.windows {
+                subdirs.push(("windows".to_owned(), windows.cache_dir.clone()));
+            }
+            if let Some(debuginfod) = &symbols.debuginfod {
+                subdirs.push(("debuginfod".to_owned(), debuginfod.cache_dir.clone()));
+            }
+        }
+        if let Some(source) = &settings.source {
+            subdirs.push(("source".to_owned(), source.cache_dir.clone()));
+        }
+        if let Some(upload) = &settings.upload {
+            subdirs.push(("upload".to_owned(), upload.dir.clone()));
+        }
+        // The configured paths usually share a prefix like `./cache/`, so
+        // compare them in the same form as the paths in the inventory.
+        let subdirs = subdirs
+            .into_iter()
+            .map(|(name, dir)| (name, absolute_path(&dir)))
+            .filter(|(_, dir)| quotas.iter().any(|quota| dir.starts_with(&quota.dir)))
+            .collect();
+        Some(Self {
+            quotas,
+            subdirs,
+            pins,
+            evictions: Mutex::new(EvictionTracker::default()),
+        })
+    }
+
+    /// The inventory of each quota manager, in the order of `self.quotas`.
+    fn read_inventories(&self) -> Result<Vec<Vec<InventoryFile>>, String> {
+        self.quotas
+            .iter()
+            .map(|quota| {
+                let db = QuotaDatabase::open(&quota.dir, &quota.db_path)?;
+                let mut files = db.files().map_err(|e| e.to_string())?;
+                for file in &mut files {
+                    file.path = absolute_path(&file.path);
+                }
+                Ok(files)
+            })
+            .collect()
+    }
+
+    /// Compares the inventory with the previous one. Call this every
+    /// [`EVICTION_POLL_INTERVAL`].
+    pub fn detect_evictions(&self) -> Result<(), String> {
+        let files: Vec<InventoryFile> = self.read_inventories()?.into_iter().flatten().collect();
+        self.evictions.lock().unwrap().update(&files);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Result<CacheStatsResponse, String> {
+        let inventories = self.read_inventories()?;
+        let quotas = self
+            .quotas
+            .iter()
+            .zip(&inventories)
+            .map(|(quota, files)| QuotaStats {
+                managed_dir: quota.dir.clone(),
+                size_limit: quota.size_limit,
+                age_limit_seconds: quota.age_limit.map(|d| d.as_secs()),
+                total_size: files.iter().map(|file| file.size).sum(),
+                file_count: files.len(),
+            })
+            .collect();
+        let mut files: Vec<InventoryFile> = inventories.into_iter().flatten().collect();
+        let recent_evictions = {
+            let mut evictions = self.evictions.lock().unwrap();
+            evictions.update(&files);
+            evictions
+                .recent
+                .iter()
+                .map(|(path, size, detected_at)| EvictionStats {
+                    path: path.clone(),
+                    size: *size,
+                    detected_seconds_ago: detected_at.elapsed().as_secs(),
+                })
+                .collect()
+        };
+
+        let mut pinned_files: Vec<InventoryFile> = self
+            .pins
+            .files()
+            .into_iter()
+            .map(|file| InventoryFile {
+                path: file.path,
+                size: file.size,
+                last_access: None,
+            })
+            .collect();
+        pinned_files.sort_by_key(|file| std::cmp::Reverse(file.size));
+        let pinned = PinnedStats {
+            total_size: pinned_files.iter().map(|file| file.size).sum(),
+            file_count: pinned_files.len(),
+            largest_files: pinned_files
+                .iter()
+                .take(LISTED_FILE_COUNT)
+                .map(FileStats::new)
+                .collect(),
+        };
+
+        let subdirectories = self
+            .subdirs
+            .iter()
+            .map(|(name, dir)| {
+                let (total_size, file_count) = files
+                    .iter()
+                    .filter(|file| file.path.starts_with(dir))
+                    .fold((0, 0), |(size, count), file| (size + file.size, count + 1));
+                SubdirectoryStats {
+                    name: name.clone(),
+                    dir: dir.clone(),
+                    total_size,
+                    file_count,
+                }
+            })
+            .collect();
+
+        files.sort_by_key(|file| file.last_access);
+        let oldest_files = files
+            .iter()
+            .take(LISTED_FILE_COUNT)
+            .map(FileStats::new)
+            .collect();
+        let newest_files = files
+            .iter()
+            .rev()
+            .take(LISTED_FILE_COUNT)
+            .map(FileStats::new)
+            .collect();
+        files.sort_by_key(|file| std::cmp::Reverse(file.size));
+        let largest_files = files
+            .iter()
+            .take(LISTED_FILE_COUNT)
+            .map(FileStats::new)
+            .collect();
+
+        Ok(CacheStatsResponse {
+            total_size: files.iter().map(|file| file.size).sum(),
+            file_count: files.len(),
+            quotas,
+            pinned,
+            subdirectories,
+            oldest_files,
+            newest_files,
+            largest_files,
+            recent_evictions,
+        })
+    }
+}
+
+impl EvictionTracker {
+    fn update(&mut self, files: &[InventoryFile]) {
+        let current: HashMap<PathBuf, u64> = files
+            .iter()
+            .map(|file| (file.path.clone(), file.size))
+            .collect();
+        if let Some(known_files) = self.known_files.take() {
+            let now = Instant::now();
+            for (path, size) in known_files {
+                if !current.contains_key(&path) && !path.exists() {
+                    if self.recent.len() == MAX_RECENT_EVICTIONS {
+                        self.recent.pop_back();
+                    }
+                    self.recent.push_front((path, size, now));
+                }
+            }
+        }
+        self.known_files = Some(current);
+    }
+}
@@ -1,14 +1,30 @@
-use std::{path::PathBuf, time::Duration};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use config::ConfigError;
 use serde::Deserialize;
 
+use crate::quota::absolute_path;
+
 #[derive(Deserialize)]
 pub struct Settings {
     pub server: ServerSettings,
     pub symbols: Option<SymbolSettings>,
     pub quota: Option<QuotaSettings>,
     pub self_profiles: Option<SelfProfilesSettings>,
+    #[serde(default)]
+    pub compression: CompressionSettings,
+    #[serde(default)]
+    pub symbolication: SymbolicationSettings,
+    pub source: Option<SourceSettings>,
+    pub upload: Option<UploadSettings>,
+    #[serde(default)]
+    pub admin: AdminSettings,
+    #[serde(default)]
+    pub heartbeat: HeartbeatSettings,
 }
 
 #[derive(Deserialize)]
@@ -20,37 +36,353 @@ pub struct SelfProfilesSettings {
 pub struct ServerSettings {
     pub host: String,
     pub port: u16,
+    /// The maximum size of a request body, as a string that's parsed by the
+    /// [parse-size crate](https://crates.io/crates/parse-size).
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_bytes")]
+    pub max_request_body_size: Option<u64>,
 }
 
-#[derive(Deserialize)]
+/// Compression levels for response bodies, per encoding. The encoding itself
+/// is negotiated with each client via `Accept-Encoding`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct CompressionSettings {
+    /// 0 (none) to 9 (best)
+    pub gzip_level: u32,
+    /// 1 (fastest) to 22 (best)
+    pub zstd_level: i32,
+    /// 0 (fastest) to 11 (best)
+    pub brotli_level: u32,
+}
+
+impl Default for CompressionSettings {
+    fn default() -> Self {
+        Self {
+            gzip_level: 2, // not tweaked
+            zstd_level: 3,
+            brotli_level: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct SymbolicationSettings {
+    /// How long a `/symbolicate/v5` request may take before we respond with
+    /// the partial results we have so far, as a string that's parsed by
+    /// [`humantime::parse_duration`](https://docs.rs/humantime/latest/humantime/fn.parse_duration.html).
+    ///
+    /// Clients can ask for a shorter deadline with the
+    /// `X-Symbolication-Deadline` request header.
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub deadline: Option<Duration>,
+}
+
+/// Settings for fetching source files for `/source/v1`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceSettings {
+    /// The directory in which fetched source files are cached. Put this
+    /// inside the quota managed directory so that old files get deleted.
+    pub cache_dir: PathBuf,
+    /// The providers from which source files are fetched, in the order in
+    /// which they're tried.
+    #[serde(default)]
+    pub providers: Vec<SourceProviderSettings>,
+}
+
+/// Where to fetch the files for mapped source paths, i.e. for paths of the
+/// form `git:<repo>:<path>:<rev>` or `cargo:<registry>:<crate>-<version>:<path>`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SourceProviderSettings {
+    /// A local clone of the git repository `repo`, e.g.
+    /// `github.com/mstange/reliost`. Files are read with `git show`.
+    Git { repo: String, checkout: PathBuf },
+    /// A local directory with the same layout as `~/.cargo/registry/src`,
+    /// i.e. `<registry>/<crate>-<version>/<path>`.
+    Cargo { registry_src_dir: PathBuf },
+    /// An HTTP server. The URL is created from `template` by replacing the
+    /// placeholders `{repo}`, `{rev}` and `{path}` for git paths, or
+    /// `{registry}`, `{crate}` and `{path}` for cargo paths. For git paths,
+    /// `repo_prefix` is stripped from the repo, and repos without this prefix
+    /// are skipped.
+    Http {
+        mapping: SourceMappingKind,
+        #[serde(default)]
+        repo_prefix: String,
+        template: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceMappingKind {
+    Git,
+    Cargo,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct SymbolSettings {
     pub breakpad: Option<BreakpadSymbolSettings>,
     pub windows: Option<WindowsSymbolSettings>,
+    pub debuginfod: Option<DebuginfodSymbolSettings>,
+    pub negative_cache: Option<NegativeCacheSettings>,
 }
 
-#[derive(Deserialize)]
+/// Remembers which files the Breakpad and Windows symbol servers don't have,
+/// so that we don't ask them again until `ttl` has passed. debuginfod servers
+/// aren't covered.
+///
+/// This is off by default: the negative cache lives in the local proxy for
+/// symbol servers, so all Breakpad and Windows downloads go through that
+/// proxy while it's enabled.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NegativeCacheSettings {
+    /// The .db file in which the missing files are stored.
+    pub db_path: PathBuf,
+    /// How long a 404 is remembered, as a string that's parsed by
+    /// [`humantime::parse_duration`](https://docs.rs/humantime/latest/humantime/fn.parse_duration.html).
+    #[serde(with = "humantime_serde")]
+    pub ttl: Duration,
+}
+
+/// Breakpad symbol files are looked up in the local `dirs` first, in the
+/// listed order, and then on the `servers`, in the listed order.
+#[derive(Debug, Clone, Deserialize)]
 pub struct BreakpadSymbolSettings {
+    /// Local directories with .sym files in the symbol server layout, which
+    /// aren't managed by us, e.g. symbols from our own builds.
     #[serde(default)]
-    pub servers: Vec<String>,
+    pub dirs: Vec<PathBuf>,
+    #[serde(default)]
+    pub servers: Vec<SymbolServerSettings>,
 
+    /// The directory into which files from `servers` are downloaded. If there
+    /// are no servers, this is searched like one of the local `dirs`.
     pub cache_dir: PathBuf,
     pub symindex_dir: Option<PathBuf>,
 }
 
-#[derive(Deserialize)]
+/// Windows symbol files are looked up in the local `dirs` first, in the
+/// listed order, and then on the `servers`, in the listed order.
+#[derive(Debug, Clone, Deserialize)]
 pub struct WindowsSymbolSettings {
+    /// Local symbol stores, i.e. directories with the same layout as a
+    /// symbol server, which aren't managed by us.
+    #[serde(default)]
+    pub dirs: Vec<PathBuf>,
+    #[serde(default)]
+    pub servers: Vec<SymbolServerSettings>,
+
+    /// The directory into which files from `servers` are downloaded.
+    pub cache_dir: PathBuf,
+}
+
+/// A symbol server, given either as a plain URL string or as a table with a
+/// `url` and the settings for our requests to this server, e.g.
+/// `{ url = "https://symbols.example.com/", bearer_token = { env = "SYMBOLS_TOKEN" }, timeout = "60s", retries = 2 }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "RawSymbolServerSettings")]
+pub struct SymbolServerSettings {
+    pub url: String,
+    /// Extra request headers. Use `bearer_token` or `basic_auth` for
+    /// credentials, so that they don't need to be in the configuration files.
+    pub headers: BTreeMap<String, String>,
+    pub bearer_token: Option<Secret>,
+    pub basic_auth: Option<BasicAuthSettings>,
+    /// The timeout for connecting to the server, and for each read while
+    /// downloading the file. Large files can take longer than this in total,
+    /// as long as the server keeps sending data.
+    pub timeout: Option<Duration>,
+    /// How often a failed request is retried. Connection errors, timeouts,
+    /// 429 and 5xx responses are retried, 404s are not.
+    pub retries: u32,
+}
+
+impl SymbolServerSettings {
+    /// Whether requests to this server need anything that wholesym can't
+    /// do by itself, i.e. whether they need to go through our upstream proxy.
+    pub fn needs_proxy(&self) -> bool {
+        !self.headers.is_empty()
+            || self.bearer_token.is_some()
+            || self.basic_auth.is_some()
+            || self.timeout.is_some()
+            || self.retries > 0
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawSymbolServerSettings {
+    Url(String),
+    Detailed {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        bearer_token: Option<Secret>,
+        basic_auth: Option<BasicAuthSettings>,
+        #[serde(default)]
+        #[serde(with = "humantime_serde")]
+        timeout: Option<Duration>,
+        #[serde(default)]
+        retries: u32,
+    },
+}
+
+impl From<RawSymbolServerSettings> for SymbolServerSettings {
+    fn from(raw: RawSymbolServerSettings) -> Self {
+        match raw {
+            RawSymbolServerSettings::Url(url) => Self {
+                url,
+                headers: BTreeMap::new(),
+                bearer_token: None,
+                basic_auth: None,
+                timeout: None,
+                retries: 0,
+            },
+            RawSymbolServerSettings::Detailed {
+                url,
+                headers,
+                bearer_token,
+                basic_auth,
+                timeout,
+                retries,
+            } => Self {
+                url,
+                headers,
+                bearer_token,
+                basic_auth,
+                timeout,
+                retries,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BasicAuthSettings {
+    pub username: String,
+    pub password: Secret,
+}
+
+/// A secret which is read at startup from an environment variable,
+/// `{ env = "NAME" }`, or from a file, `{ file = "/run/secrets/name" }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Secret {
+    Env(String),
+    File(PathBuf),
+}
+
+impl Secret {
+    pub fn read(&self) -> Result<String, String> {
+        match self {
+            Secret::Env(name) => std::env::var(name)
+                .map_err(|e| format!("Could not read environment variable {name}: {e}")),
+            Secret::File(path) => std::fs::read_to_string(path)
+                .map(|s| s.trim_end().to_owned())
+                .map_err(|e| format!("Could not read secret file {path:?}: {e}")),
+        }
+    }
+}
+
+/// Settings for `/upload`, which accepts Breakpad symbol files from our own
+/// builds.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UploadSettings {
+    /// The directory into which uploaded symbol files are written, in the
+    /// symbol server layout. The symbol manager searches this directory.
+    pub dir: PathBuf,
+    /// The tokens which clients send in the `Auth-Token` or
+    /// `Authorization: Bearer` header, as secrets. Uploads are rejected if
+    /// this is empty.
+    #[serde(default)]
+    pub tokens: Vec<Secret>,
+    /// The maximum size of an upload, both compressed and uncompressed, as a
+    /// string that's parsed by the [parse-size crate](https://crates.io/crates/parse-size).
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_bytes")]
+    pub max_size: Option<u64>,
+    /// If true, uploaded files are not registered with the quota manager, so
+    /// they are never evicted. `dir` should then be outside of the quota
+    /// managed directory.
+    #[serde(default)]
+    pub exempt_from_eviction: bool,
+}
+
+/// Linux debug info from debuginfod servers. Files are looked up by their
+/// ELF build ID, so only libraries whose code ID is known can be found.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DebuginfodSymbolSettings {
+    /// Servers in addition to the ones in the `DEBUGINFOD_URLS` environment
+    /// variable.
     #[serde(default)]
     pub servers: Vec<String>,
 
+    /// The directory into which downloaded files are stored. If debuginfod
+    /// is installed, wholesym uses the system's client, which stores files
+    /// in the `DEBUGINFOD_CACHE_PATH` environment variable instead, so set
+    /// that to the same directory.
     pub cache_dir: PathBuf,
 }
 
+impl DebuginfodSymbolSettings {
+    /// Checks that the system's debuginfod client, if it's used, caches
+    /// files in `cache_dir`.
+    pub fn validate(&self) -> Result<(), String> {
+        self.validate_cache_path(std::env::var_os("DEBUGINFOD_CACHE_PATH").as_deref())
+    }
+
+    fn validate_cache_path(&self, cache_path: Option<&std::ffi::OsStr>) -> Result<(), String> {
+        match cache_path {
+            Some(cache_path) if absolute_path(Path::new(cache_path)) != absolute_path(&self.cache_dir) => {
+                Err(format!(
+                    "DEBUGINFOD_CACHE_PATH {cache_path:?} must be the same directory as symbols.debuginfod.cache_dir {:?}",
+                    self.cache_dir
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Settings for the `/admin/` endpoints.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AdminSettings {
+    /// The tokens which clients send in the `Auth-Token` or
+    /// `Authorization: Bearer` header, as secrets. The admin endpoints reject
+    /// all requests if this is empty.
+    #[serde(default)]
+    pub tokens: Vec<Secret>,
+}
+
+/// Settings for the checks which `/__heartbeat__` runs.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HeartbeatSettings {
+    /// The heartbeat fails if less disk space than this is available for any
+    /// of the cache directories, like `quota.min_free_space`, e.g. "1 GB" or
+    /// "5%".
+    #[serde(default)]
+    pub min_free_space: Option<MinFreeSpace>,
+    /// If set, the heartbeat also checks that the symbol servers respond,
+    /// and reuses the result for this long. A string that's parsed by
+    /// [`humantime::parse_duration`](https://docs.rs/humantime/latest/humantime/fn.parse_duration.html).
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub upstream_check_interval: Option<Duration>,
+}
+
 /// Settings for automatic file deletion
 #[derive(Debug, Clone, Deserialize)]
 pub struct QuotaSettings {
     /// The root of the managed directory tree.
     pub managed_dir: PathBuf,
-    /// The .db file in which the list of files should be stored. Must be outside of `managed_dir`.
+    /// The .db file in which the list of files should be stored. Must be
+    /// outside of `managed_dir` and of the `caches` directories. If it can't
+    /// be opened, it's moved aside. If it's new, or if it lists files which
+    /// belong to one of the `caches` now, the list is rebuilt from the files
+    /// in `managed_dir`.
     pub db_path: PathBuf,
     /// The maximum size of the managed directory, as a string that's
     /// parsed by the [parse-size crate](https://crates.io/crates/parse-size).
@@ -62,6 +394,117 @@ pub struct QuotaSettings {
     #[serde(default)]
     #[serde(with = "humantime_serde")]
     pub age_limit: Option<Duration>,
+    /// The free space to keep on the file system of `managed_dir`, e.g.
+    /// "5 GB" or "10%". If there's less, files are evicted even if the
+    /// managed directory is below `size_limit`.
+    #[serde(default)]
+    pub min_free_space: Option<MinFreeSpace>,
+    /// Directories with their own size and age limits, e.g. so that large
+    /// Windows PDBs don't evict the small breakpad sym files. Files in these
+    /// directories don't count toward the limits above, even if they are
+    /// inside `managed_dir`.
+    #[serde(default)]
+    pub caches: Vec<CacheQuotaSettings>,
+    /// Libraries whose files are evicted last, as `<debugName>` patterns in
+    /// which `*` matches any number of characters, e.g. `libxul*.so`, or as
+    /// `<debugName>/<debugId>`. Their files still count toward the limits.
+    #[serde(default)]
+    pub pinned: Vec<String>,
+    /// The SQLite database in which the pins added through `/admin/pins` are
+    /// stored. Without it, libraries can only be pinned in `pinned`.
+    #[serde(default)]
+    pub pins_db_path: Option<PathBuf>,
+}
+
+impl QuotaSettings {
+    /// Checks that no .db file, including `pins_db_path`, is inside a managed
+    /// directory, where it would be counted and evicted like a cached file,
+    /// and that no two quota managers share a .db file.
+    pub fn validate(&self) -> Result<(), String> {
+        let caches: Vec<(&Path, &Path)> = std::iter::once((&self.managed_dir, &self.db_path))
+            .chain(self.caches.iter().map(|cache| (&cache.dir, &cache.db_path)))
+            .map(|(dir, db_path)| (dir.as_path(), db_path.as_path()))
+            .collect();
+        let mut db_paths = HashSet::new();
+        let all_db_paths = caches
+            .iter()
+            .map(|(_, db_path)| *db_path)
+            .chain(self.pins_db_path.as_deref());
+        for db_path in all_db_paths {
+            let absolute_db_path = absolute_path(db_path);
+            if let Some((dir, _)) = caches
+                .iter()
+                .find(|(dir, _)| absolute_db_path.starts_with(absolute_path(dir)))
+            {
+                return Err(format!(
+                    "The database {db_path:?} must be outside of the managed directory {dir:?}"
+                ));
+            }
+            if !db_paths.insert(absolute_db_path) {
+                return Err(format!("The database {db_path:?} is used more than once"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Settings for automatic file deletion in one cache directory, in
+/// `[[quota.caches]]`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheQuotaSettings {
+    /// The cache directory, e.g. the `cache_dir` of `[symbols.windows]`.
+    pub dir: PathBuf,
+    /// The .db file in which the list of files should be stored. Must be
+    /// outside of all managed directories, and different from the other .db
+    /// files.
+    pub db_path: PathBuf,
+    /// The maximum size of `dir`, parsed like `quota.size_limit`.
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_bytes")]
+    pub size_limit: Option<u64>,
+    /// The maximum age of each file in `dir`, parsed like `quota.age_limit`.
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub age_limit: Option<Duration>,
+    /// The free space to keep on the file system of `dir`, like
+    /// `quota.min_free_space`.
+    #[serde(default)]
+    pub min_free_space: Option<MinFreeSpace>,
+}
+
+/// An amount of free disk space, either in bytes or as a percentage of the
+/// file system's size.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum MinFreeSpace {
+    Bytes(u64),
+    Percent(f64),
+}
+
+impl MinFreeSpace {
+    /// The free space in bytes, on a file system with `total_space` bytes.
+    pub fn bytes(&self, total_space: u64) -> u64 {
+        match *self {
+            MinFreeSpace::Bytes(bytes) => bytes,
+            MinFreeSpace::Percent(percent) => (total_space as f64 * percent / 100.0) as u64,
+        }
+    }
+}
+
+impl TryFrom<String> for MinFreeSpace {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.trim().strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<f64>() {
+                Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(Self::Percent(percent)),
+                _ => Err(format!("{s} is not a percentage between 0% and 100%")),
+            },
+            None => parse_size::parse_size(&s)
+                .map(Self::Bytes)
+                .map_err(|e| format!("{s} is not a size: {e}")),
+        }
+    }
 }
 
 fn deserialize_bytes<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
@@ -141,3 +584,75 @@ pub fn get_configuration() -> Result<Settings, ConfigError> {
         .build()?;
     settings.try_deserialize::<Settings>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_min_free_space() {
+        assert_eq!(
+            MinFreeSpace::try_from("5 GB".to_string()),
+            Ok(MinFreeSpace::Bytes(5_000_000_000))
+        );
+        let percent = MinFreeSpace::try_from("10%".to_string()).unwrap();
+        assert_eq!(percent, MinFreeSpace::Percent(10.0));
+        assert_eq!(percent.bytes(2_000_000), 200_000);
+        assert!(MinFreeSpace::try_from("110%".to_string()).is_err());
+        assert!(MinFreeSpace::try_from("lots".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_validates_quota_db_paths() {
+        let cache = |dir: &str, db_path: &str| CacheQuotaSettings {
+            dir: dir.into(),
+            db_path: db_path.into(),
+            size_limit: None,
+            age_limit: None,
+            min_free_space: None,
+        };
+        let mut quota = QuotaSettings {
+            managed_dir: "./cache/symbols".into(),
+            db_path: "./cache/symbols.db".into(),
+            size_limit: None,
+            age_limit: None,
+            min_free_space: None,
+            caches: vec![cache("./cache/symbols/windows", "./cache/windows.db")],
+            pinned: vec!["xul.pdb".into()],
+            pins_db_path: Some("./cache/pins.db".into()),
+        };
+        assert_eq!(quota.validate(), Ok(()));
+
+        quota.caches = vec![cache("./cache/symbols/windows", "cache/symbols.db")];
+        assert!(quota.validate().unwrap_err().contains("more than once"));
+        quota.caches = vec![cache(
+            "./cache/symbols/windows",
+            "./cache/symbols/windows.db",
+        )];
+        assert!(quota.validate().unwrap_err().contains("outside"));
+        quota.caches = vec![cache("./cache/windows", "./cache/windows/inventory.db")];
+        assert!(quota.validate().unwrap_err().contains("outside"));
+
+        quota.caches = Vec::new();
+        quota.pins_db_path = Some("./cache/symbols/pins.db".into());
+        assert!(quota.validate().unwrap_err().contains("outside"));
+        quota.pins_db_path = Some("./cache/symbols.db".into());
+        assert!(quota.validate().unwrap_err().contains("more than once"));
+    }
+
+    #[test]
+    fn test_validates_debuginfod_cache_path() {
+        let debuginfod = DebuginfodSymbolSettings {
+            servers: Vec::new(),
+            cache_dir: "./cache/debuginfod".into(),
+        };
+        assert_eq!(debuginfod.validate_cache_path(None), Ok(()));
+        assert_eq!(
+            debuginfod.validate_cache_path(Some("cache/debuginfod".as_ref())),
+            Ok(())
+        );
+        assert!(debuginfod
+            .validate_cache_path(Some("/var/cache/debuginfod".as_ref()))
+            .is_err());
+    }
+}
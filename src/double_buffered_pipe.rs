@@ -17,7 +17,13 @@ impl RemoteBufWriter {
             .spawn(move || {
                 let mut writer = writer;
                 while receiver.swap_blocking() {
-                    let _ = writer.write_all(&receiver);
+                    if writer.write_all(&receiver).is_err() {
+                        // The underlying writer is broken, e.g. because the
+                        // client has disconnected. Dropping the receiver makes
+                        // the next swap on the sender side fail, so that the
+                        // producer stops early.
+                        return;
+                    }
                     receiver.clear();
                 }
                 let _ = writer.flush();
@@ -54,3 +60,28 @@ impl Write for RemoteBufWriter {
         self.swap_chunk()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct BrokenWriter;
+
+    impl Write for BrokenWriter {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_fails_after_underlying_writer_breaks() {
+        let mut writer = RemoteBufWriter::with_capacity(16, BrokenWriter);
+        let result = (0..100).try_for_each(|_| writer.write_all(&[0; 16]));
+        let error = result.expect_err("Writing should fail eventually");
+        assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// How many finished downloads we remember for `/admin/downloads`.
+const MAX_RECENT_DOWNLOADS: usize = 50;
+
+/// The downloads which the symbol manager is running right now, and the ones
+/// which finished recently, as reported to its observer.
+#[derive(Default)]
+pub struct DownloadTracker {
+    in_flight: Mutex<HashMap<u64, InFlightDownload>>,
+    recent: Mutex<VecDeque<FinishedDownload>>,
+}
+
+struct InFlightDownload {
+    url: String,
+    /// When we started connecting to the server.
+    started_at: Instant,
+    bytes_so_far: u64,
+    total_bytes: Option<u64>,
+}
+
+struct FinishedDownload {
+    url: String,
+    outcome: DownloadOutcome,
+    bytes: u64,
+    duration: Duration,
+    finished_at: Instant,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "outcome")]
+pub enum DownloadOutcome {
+    Completed,
+    Failed { error: String },
+    Canceled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadStatus {
+    pub url: String,
+    pub bytes_so_far: u64,
+    /// The size from the Content-Length header, if the server sent one.
+    pub total_bytes: Option<u64>,
+    pub elapsed_seconds: f64,
+    /// Extrapolated from the average download speed so far.
+    pub estimated_seconds_remaining: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FinishedDownloadStatus {
+    pub url: String,
+    #[serde(flatten)]
+    pub outcome: DownloadOutcome,
+    /// The uncompressed size for completed downloads, and the bytes received
+    /// so far for the others.
+    pub bytes: u64,
+    pub duration_seconds: f64,
+    pub finished_seconds_ago: f64,
+}
+
+impl InFlightDownload {
+    fn status(&self, now: Instant) -> DownloadStatus {
+        let elapsed = now.duration_since(self.started_at).as_secs_f64();
+        let estimated_seconds_remaining = match self.total_bytes {
+            Some(total_bytes) if self.bytes_so_far > 0 => {
+                let remaining_bytes = total_bytes.saturating_sub(self.bytes_so_far);
+                Some(elapsed * remaining_bytes as f64 / self.bytes_so_far as f64)
+            }
+            _ => None,
+        };
+        DownloadStatus {
+            url: self.url.clone(),
+            bytes_so_far: self.bytes_so_far,
+            total_bytes: self.total_bytes,
+            elapsed_seconds: elapsed,
+            estimated_seconds_remaining,
+        }
+    }
+}
+
+impl DownloadTracker {
+    pub fn on_connect(&self, download_id: u64, url: &str) {
+        self.in_flight.lock().unwrap().insert(
+            download_id,
+            InFlightDownload {
+                url: url.to_owned(),
+                started_at: Instant::now(),
+                bytes_so_far: 0,
+                total_bytes: None,
+            },
+        );
+    }
+
+    pub fn on_progress(&self, download_id: u64, bytes_so_far: u64, total_bytes: Option<u64>) {
+        if let Some(download) = self.in_flight.lock().unwrap().get_mut(&download_id) {
+            download.bytes_so_far = bytes_so_far;
+            download.total_bytes = total_bytes;
+        }
+    }
+
+    pub fn url(&self, download_id: u64) -> Option<String> {
+        let in_flight = self.in_flight.lock().unwrap();
+        Some(in_flight.get(&download_id)?.url.clone())
+    }
+
+    /// Moves a download to the history, and returns its URL.
+    pub fn on_finished(
+        &self,
+        download_id: u64,
+        outcome: DownloadOutcome,
+        uncompressed_size_in_bytes: Option<u64>,
+    ) -> Option<String> {
+        let download = self.in_flight.lock().unwrap().remove(&download_id)?;
+        let finished_at = Instant::now();
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == MAX_RECENT_DOWNLOADS {
+            recent.pop_back();
+        }
+        recent.push_front(FinishedDownload {
+            url: download.url.clone(),
+            outcome,
+            bytes: uncompressed_size_in_bytes.unwrap_or(download.bytes_so_far),
+            duration: finished_at.duration_since(download.started_at),
+            finished_at,
+        });
+        Some(download.url)
+    }
+
+    pub fn in_flight(&self) -> Vec<DownloadStatus> {
+        self.in_flight_matching(|_| true)
+    }
+
+    /// The in-flight downloads whose URL matches `predicate`, longest-running
+    /// first.
+    pub fn in_flight_matching(&self, predicate: impl Fn(&str) -> bool) -> Vec<DownloadStatus> {
+        let now = Instant::now();
+        let mut downloads: Vec<DownloadStatus> = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|download| predicate(&download.url))
+            .map(|download| download.status(now))
+            .collect();
+        downloads.sort_by(|a, b| b.elapsed_seconds.total_cmp(&a.elapsed_seconds));
+        downloads
+    }
+
+    /// The recently finished downloads, most recent first.
+    pub fn recent(&self) -> Vec<FinishedDownloadStatus> {
+        let now = Instant::now();
+        self.recent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|download| FinishedDownloadStatus {
+                url: download.url.clone(),
+                outcome: download.outcome.clone(),
+                bytes: download.bytes,
+                duration_seconds: download.duration.as_secs_f64(),
+                finished_seconds_ago: now.duration_since(download.finished_at).as_secs_f64(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimates_remaining_time() {
+        let started_at = Instant::now();
+        let download = InFlightDownload {
+            url: "https://symbols.example.com/xul.pdb/ID/xul.sym".into(),
+            started_at,
+            bytes_so_far: 250,
+            total_bytes: Some(1000),
+        };
+        let status = download.status(started_at + Duration::from_secs(10));
+        assert_eq!(status.elapsed_seconds, 10.0);
+        assert_eq!(status.estimated_seconds_remaining, Some(30.0));
+
+        let download = InFlightDownload {
+            total_bytes: None,
+            ..download
+        };
+        let status = download.status(started_at + Duration::from_secs(10));
+        assert_eq!(status.estimated_seconds_remaining, None);
+    }
+
+    #[test]
+    fn test_keeps_recent_downloads() {
+        let tracker = DownloadTracker::default();
+        for download_id in 0..(MAX_RECENT_DOWNLOADS as u64 + 5) {
+            tracker.on_connect(download_id, &format!("https://example.com/{download_id}"));
+            tracker.on_progress(download_id, 10, Some(20));
+            tracker.on_finished(
+                download_id,
+                DownloadOutcome::Failed {
+                    error: "timeout".into(),
+                },
+                None,
+            );
+        }
+        assert!(tracker.in_flight().is_empty());
+        let recent = tracker.recent();
+        assert_eq!(recent.len(), MAX_RECENT_DOWNLOADS);
+        assert_eq!(
+            recent[0].url,
+            format!("https://example.com/{}", MAX_RECENT_DOWNLOADS + 4)
+        );
+        assert_eq!(recent[0].bytes, 10);
+    }
+}
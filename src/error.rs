@@ -0,0 +1,177 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::Serialize;
+
+use crate::symbolication::ModuleError;
+
+/// The error type returned by all route handlers.
+///
+/// Every variant maps to an HTTP status code and a machine-readable error
+/// code, and is sent to the client as a JSON body of the form
+/// `{ "error": { "code": "...", "message": "..." } }`.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("The request body is not valid UTF-8: {0}")]
+    InvalidUtf8(#[from] std::str::Utf8Error),
+
+    #[error("The request body is not a valid request: {0}")]
+    InvalidRequest(serde_json::Error),
+
+    #[error("The request body has an unsupported Content-Encoding: {0:?}")]
+    UnsupportedContentEncoding(String),
+
+    #[error("The request body could not be decompressed: {0}")]
+    InvalidContentEncoding(std::io::Error),
+
+    #[error("Invalid request path: {0}")]
+    InvalidPath(String),
+
+    #[error("Invalid source file request: {0}")]
+    InvalidSourcePath(String),
+
+    #[error("Invalid upload: {0}")]
+    InvalidUpload(String),
+
+    #[error("Invalid {name} header: {message}")]
+    InvalidHeader { name: &'static str, message: String },
+
+    #[error("The request body could not be read: {0}")]
+    Payload(#[from] actix_web::error::PayloadError),
+
+    #[error("The request body exceeds the limit of {limit} bytes")]
+    PayloadTooLarge { limit: usize },
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Not acceptable: {0}")]
+    NotAcceptable(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
+
+    #[error("Bad gateway: {0}")]
+    BadGateway(String),
+
+    #[error("Could not serialize the response: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Could not write the response: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    /// The machine-readable error code which is sent in the response body.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidUtf8(_) => "invalid_utf8",
+            ApiError::InvalidRequest(_) => "invalid_request",
+            ApiError::UnsupportedContentEncoding(_) => "unsupported_content_encoding",
+            ApiError::InvalidContentEncoding(_) => "invalid_content_encoding",
+            ApiError::InvalidPath(_) => "invalid_path",
+            ApiError::InvalidSourcePath(_) => "invalid_source_path",
+            ApiError::InvalidUpload(_) => "invalid_upload",
+            ApiError::InvalidHeader { .. } => "invalid_header",
+            ApiError::Payload(_) => "invalid_payload",
+            ApiError::PayloadTooLarge { .. } => "payload_too_large",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotAcceptable(_) => "not_acceptable",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::ServiceUnavailable(_) => "service_unavailable",
+            ApiError::BadGateway(_) => "bad_gateway",
+            ApiError::Serialization(_) => "serialization_failed",
+            ApiError::Io(_) => "io_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// Whether this error was caused by the client going away while we were
+    /// writing the response.
+    pub fn is_client_disconnect(&self) -> bool {
+        let kind = match self {
+            ApiError::Io(e) => Some(e.kind()),
+            ApiError::Serialization(e) => e.io_error_kind(),
+            _ => None,
+        };
+        kind == Some(std::io::ErrorKind::BrokenPipe)
+    }
+}
+
+impl From<ModuleError> for ApiError {
+    /// Only libraries which nobody has symbols for are "not found". Other
+    /// errors, e.g. failed downloads or symbol files which couldn't be
+    /// parsed, shouldn't look like a permanent answer to clients and caches.
+    fn from(error: ModuleError) -> Self {
+        if error.is_not_found() {
+            ApiError::NotFound(error.message)
+        } else if error.is_download_error() {
+            ApiError::BadGateway(error.message)
+        } else {
+            ApiError::Internal(error.message)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+    error: ErrorBody<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: String,
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidUtf8(_)
+            | ApiError::InvalidRequest(_)
+            | ApiError::InvalidContentEncoding(_)
+            | ApiError::InvalidPath(_)
+            | ApiError::InvalidSourcePath(_)
+            | ApiError::InvalidUpload(_)
+            | ApiError::InvalidHeader { .. }
+            | ApiError::Payload(_) => StatusCode::BAD_REQUEST,
+            ApiError::UnsupportedContentEncoding(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotAcceptable(_) => StatusCode::NOT_ACCEPTABLE,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Serialization(_) | ApiError::Io(_) | ApiError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        if status.is_server_error() {
+            tracing::error!(code = self.code(), error = %self, "Request failed");
+        }
+        HttpResponse::build(status).json(ErrorResponse {
+            error: ErrorBody {
+                code: self.code(),
+                message: self.to_string(),
+            },
+        })
+    }
+}
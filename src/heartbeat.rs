@@ -0,0 +1,343 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::configuration::{MinFreeSpace, Settings};
+use crate::quota::QuotaHealth;
+
+/// How long we wait for a symbol server to respond.
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Makes the names of the files which we write to check that a directory is
+/// writable unique, because heartbeat requests can run concurrently.
+static NEXT_PROBE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The checks behind `/__heartbeat__`.
+pub struct HeartbeatChecker {
+    /// The directories we write to, by the name of their setting.
+    dirs: Vec<(String, PathBuf)>,
+    /// The SQLite databases we write to, by the name of their setting.
+    db_paths: Vec<(String, PathBuf)>,
+    /// The databases of the quota managers, by the name of their check.
+    quota_db_paths: Vec<(String, PathBuf)>,
+    quota_health: Arc<QuotaHealth>,
+    min_free_space: Option<MinFreeSpace>,
+    upstream: Option<UpstreamChecks>,
+}
+
+struct UpstreamChecks {
+    urls: Vec<String>,
+    interval: Duration,
+    client: reqwest::Client,
+    last_result: Mutex<Option<(Instant, Vec<Check>)>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    /// Something is wrong, but we can still serve requests.
+    Warning,
+    /// We can't serve requests properly. The heartbeat fails.
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    name: String,
+    status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl Check {
+    fn new(name: &str, result: Result<(), String>, failure_status: CheckStatus) -> Self {
+        let (status, message) = match result {
+            Ok(()) => (CheckStatus::Ok, None),
+            Err(message) => (failure_status, Some(message)),
+        };
+        Self {
+            name: name.to_owned(),
+            status,
+            message,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HeartbeatResponse {
+    /// The worst status of all checks.
+    pub status: CheckStatus,
+    pub checks: Vec<Check>,
+}
+
+impl HeartbeatChecker {
+    pub fn new(settings: &Settings) -> Self {
+        let mut dirs = Vec::new();
+        let mut db_paths = Vec::new();
+        let mut quota_db_paths = Vec::new();
+        let mut urls = Vec::new();
+        if let Some(symbols) = &settings.symbols {
+            if let Some(breakpad) = &symbols.breakpad {
+                dirs.push(("breakpad.cache_dir".into(), breakpad.cache_dir.clone()));
+                if let Some(symindex_dir) = &breakpad.symindex_dir {
+                    dirs.push(("breakpad.symindex_dir".into(), symindex_dir.clone()));
+                }
+                urls.extend(breakpad.servers.iter().map(|server| server.url.clone()));
+            }
+            if let Some(windows) = &symbols.windows {
+                dirs.push(("windows.cache_dir".into(), windows.cache_dir.clone()));
+                urls.extend(windows.servers.iter().map(|server| server.url.clone()));
+            }
+            if let Some(debuginfod) = &symbols.debuginfod {
+                dirs.push(("debuginfod.cache_dir".into(), debuginfod.cache_dir.clone()));
+                urls.extend(debuginfod.servers.iter().cloned());
+            }
+            if let Some(negative_cache) = &symbols.negative_cache {
+                db_paths.push((
+                    "negative_cache.db_path".into(),
+                    negative_cache.db_path.clone(),
+                ));
+            }
+        }
+        if let Some(source) = &settings.source {
+            dirs.push(("source.cache_dir".into(), source.cache_dir.clone()));
+        }
+        if let Some(upload) = &settings.upload {
+            dirs.push(("upload.dir".into(), upload.dir.clone()));
+        }
+        if let Some(quota) = &settings.quota {
+            dirs.push(("quota.managed_dir".into(), quota.managed_dir.clone()));
+            db_paths.push(("quota.db_path".into(), quota.db_path.clone()));
+            quota_db_paths.push(("quota_manager".into(), quota.db_path.clone()));
+            if let Some(pins_db_path) = &quota.pins_db_path {
+                db_paths.push(("quota.pins_db_path".into(), pins_db_path.clone()));
+            }
+            for (i, cache) in quota.caches.iter().enumerate() {
+                dirs.push((format!("quota.caches[{i}].dir"), cache.dir.clone()));
+                db_paths.push((format!("quota.caches[{i}].db_path"), cache.db_path.clone()));
+                quota_db_paths.push((format!("quota_manager.caches[{i}]"), cache.db_path.clone()));
+            }
+        }
+        urls.sort();
+        urls.dedup();
+
+        let upstream = settings
+            .heartbeat
+            .upstream_check_interval
+            .filter(|_| !urls.is_empty())
+            .map(|interval| UpstreamChecks {
+                urls,
+                interval,
+                client: reqwest::Client::builder()
+                    .timeout(UPSTREAM_TIMEOUT)
+                    .build()
+                    .expect("Could not create HTTP client for heartbeat checks"),
+                last_result: Mutex::new(None),
+            });
+
+        Self {
+            dirs,
+            db_paths,
+            quota_db_paths,
+            quota_health: Arc::default(),
+            min_free_space: settings.heartbeat.min_free_space,
+            upstream,
+        }
+    }
+
+    /// Reports the quota managers which couldn't be started, or whose limits
+    /// are no longer being enforced.
+    pub fn with_quota_health(mut self, quota_health: Arc<QuotaHealth>) -> Self {
+        self.quota_health = quota_health;
+        self
+    }
+
+    pub async fn check(&self) -> HeartbeatResponse {
+        let dirs = self.dirs.clone();
+        let db_paths = self.db_paths.clone();
+        let quota_db_paths = self.quota_db_paths.clone();
+        let quota_health = Arc::clone(&self.quota_health);
+        let min_free_space = self.min_free_space;
+        let mut checks = tokio::task::spawn_blocking(move || {
+            check_local(
+                &dirs,
+                &db_paths,
+                &quota_db_paths,
+                &quota_health,
+                min_free_space,
+            )
+        })
+        .await
+        .unwrap_or_else(|e| {
+            vec![Check::new(
+                "local",
+                Err(format!("The checks panicked: {e}")),
+                CheckStatus::Error,
+            )]
+        });
+        if let Some(upstream) = &self.upstream {
+            checks.extend(upstream.check().await);
+        }
+
+        let status = checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Ok);
+        HeartbeatResponse { status, checks }
+    }
+}
+
+impl UpstreamChecks {
+    /// Checks that each server responds at all. Any HTTP status counts, since
+    /// the server's root URL usually isn't a file. A server which doesn't
+    /// respond is only a warning, because the others may still have the
+    /// symbols we need.
+    ///
+    /// The servers are checked concurrently, so a refresh takes at most
+    /// [`UPSTREAM_TIMEOUT`]. Heartbeats which arrive meanwhile wait for it
+    /// instead of starting their own.
+    async fn check(&self) -> Vec<Check> {
+        let mut last_result = self.last_result.lock().await;
+        if let Some((checked_at, checks)) = last_result.as_ref() {
+            if checked_at.elapsed() < self.interval {
+                return checks.clone();
+            }
+        }
+
+        let requests: Vec<_> = self
+            .urls
+            .iter()
+            .map(|url| {
+                let request = self.client.get(url).send();
+                let url = url.clone();
+                tokio::spawn(async move {
+                    let result = match request.await {
+                        Ok(_) => Ok(()),
+                        Err(e) => Err(e.to_string()),
+                    };
+                    Check::new(&format!("upstream:{url}"), result, CheckStatus::Warning)
+                })
+            })
+            .collect();
+        let mut checks = Vec::new();
+        for (url, request) in self.urls.iter().zip(requests) {
+            checks.push(request.await.unwrap_or_else(|e| {
+                Check::new(
+                    &format!("upstream:{url}"),
+                    Err(format!("The check panicked: {e}")),
+                    CheckStatus::Warning,
+                )
+            }));
+        }
+        *last_result = Some((Instant::now(), checks.clone()));
+        checks
+    }
+}
+
+fn check_local(
+    dirs: &[(String, PathBuf)],
+    db_paths: &[(String, PathBuf)],
+    quota_db_paths: &[(String, PathBuf)],
+    quota_health: &QuotaHealth,
+    min_free_space: Option<MinFreeSpace>,
+) -> Vec<Check> {
+    let mut checks = Vec::new();
+    for (name, dir) in dirs {
+        checks.push(Check::new(
+            name,
+            check_dir_writable(dir),
+            CheckStatus::Error,
+        ));
+        if let Some(min_free_space) = min_free_space {
+            checks.push(Check::new(
+                &format!("{name}.free_space"),
+                check_free_space(dir, min_free_space),
+                CheckStatus::Error,
+            ));
+        }
+    }
+    for (name, db_path) in db_paths {
+        checks.push(Check::new(
+            name,
+            check_db_writable(db_path),
+            CheckStatus::Error,
+        ));
+    }
+    // samply-quota-manager doesn't tell us whether its thread is still
+    // running, so we check that our own quota checks, which tell it to evict
+    // files, still finish, and that its database can be read.
+    for (name, quota_db_path) in quota_db_paths {
+        checks.push(Check::new(
+            name,
+            quota_health.check(quota_db_path),
+            CheckStatus::Error,
+        ));
+        checks.push(Check::new(
+            &format!("{name}.db"),
+            check_db_readable(quota_db_path),
+            CheckStatus::Error,
+        ));
+    }
+    checks
+}
+
+/// Creates the directory if needed, because most of them are only created
+/// once the first file is stored.
+fn check_dir_writable(dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Could not create {dir:?}: {e}"))?;
+    let probe = dir.join(format!(
+        ".heartbeat-{}-{}",
+        std::process::id(),
+        NEXT_PROBE_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&probe, b"").map_err(|e| format!("Could not write to {dir:?}: {e}"))?;
+    std::fs::remove_file(&probe).map_err(|e| format!("Could not delete {probe:?}: {e}"))
+}
+
+fn check_free_space(dir: &Path, min_free_space: MinFreeSpace) -> Result<(), String> {
+    let stats =
+        fs4::statvfs(dir).map_err(|e| format!("Could not get the free space for {dir:?}: {e}"))?;
+    let available = stats.available_space();
+    let min_free_space = min_free_space.bytes(stats.total_space());
+    if available < min_free_space {
+        return Err(format!(
+            "Only {available} bytes are available for {dir:?}, less than {min_free_space}"
+        ));
+    }
+    Ok(())
+}
+
+fn check_db_writable(db_path: &Path) -> Result<(), String> {
+    if db_path.exists() {
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(db_path)
+            .map(|_| ())
+            .map_err(|e| format!("Could not open {db_path:?} for writing: {e}"))
+    } else {
+        let dir = db_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        check_dir_writable(dir)
+    }
+}
+
+fn check_db_readable(db_path: &Path) -> Result<(), String> {
+    let connection = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Could not open {db_path:?}: {e}"))?;
+    connection
+        .query_row("SELECT count(*) FROM sqlite_master", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .map(|_| ())
+        .map_err(|e| format!("Could not query {db_path:?}: {e}"))
+}
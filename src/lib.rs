@@ -1,9 +1,27 @@
 mod async_double_buffer;
+mod auth;
+mod cache_stats;
 mod channel_writer;
 pub mod configuration;
 mod double_buffered_pipe;
+mod downloads;
+pub mod error;
+mod heartbeat;
 pub mod logging;
+mod metrics;
+mod negative_cache;
+mod pins;
+mod prefetch;
+mod quota;
+mod quota_db;
+mod request_body;
+mod response_body;
 pub mod routes;
+mod source_files;
 pub mod startup;
+mod symbol_files;
 pub mod symbol_manager;
 pub mod symbol_manager_observer;
+pub mod symbolication;
+mod upload;
+mod upstream_proxy;
@@ -10,17 +10,20 @@ async fn main() -> std::io::Result<()> {
     init_subscriber(subscriber);
 
     let settings = get_configuration().expect("Failed to read configuration");
-    let (server, quota_manager) = run(
+    let (server, quota_managers, upstream_proxy) = run(
         TcpListener::bind((settings.server.host.as_str(), settings.server.port))?,
         settings,
     )?;
 
-    server.await?;
+    let result = server.await;
 
-    if let Some(quota_manager) = quota_manager {
-        // Shut down the quota manager file deletion thread.
-        quota_manager.finish().await;
+    // Downloads which were still running are over, so the upstream proxy
+    // can stop too.
+    if let Some(upstream_proxy) = upstream_proxy {
+        upstream_proxy.stop(true).await;
     }
+    // Shut down the quota manager file deletion threads.
+    quota_managers.finish().await;
 
-    Ok(())
+    result
 }
@@ -0,0 +1,290 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use actix_web::{
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+};
+use bytes::Bytes;
+use prometheus::{
+    exponential_buckets, Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts,
+    Registry, TextEncoder,
+};
+
+/// The metrics which we serve at `/metrics`, in the Prometheus text format.
+pub struct Metrics {
+    registry: Registry,
+    downloads: IntCounterVec,
+    download_bytes: IntCounterVec,
+    download_time_until_headers: HistogramVec,
+    download_duration: HistogramVec,
+    download_failures: IntCounterVec,
+    file_hits: IntCounter,
+    file_misses: IntCounter,
+    request_duration: HistogramVec,
+    response_size: HistogramVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = Registry::new_custom(Some("reliost".into()), None).unwrap();
+        let downloads = IntCounterVec::new(
+            Opts::new("downloads_total", "Completed symbol file downloads"),
+            &["host"],
+        )
+        .unwrap();
+        let download_bytes = IntCounterVec::new(
+            Opts::new(
+                "download_bytes_total",
+                "Uncompressed size of the completed symbol file downloads",
+            ),
+            &["host"],
+        )
+        .unwrap();
+        let download_time_until_headers = HistogramVec::new(
+            HistogramOpts::new(
+                "download_time_until_headers_seconds",
+                "Time until the upstream server sent the response headers",
+            )
+            .buckets(exponential_buckets(0.01, 4.0, 8).unwrap()),
+            &["host"],
+        )
+        .unwrap();
+        let download_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "download_duration_seconds",
+                "Time until a symbol file download completed",
+            )
+            .buckets(exponential_buckets(0.01, 4.0, 10).unwrap()),
+            &["host"],
+        )
+        .unwrap();
+        let download_failures = IntCounterVec::new(
+            Opts::new("download_failures_total", "Failed symbol file downloads"),
+            &["host", "kind"],
+        )
+        .unwrap();
+        let file_hits = IntCounter::new(
+            "file_hits_total",
+            "Symbol files which were found in a local directory or cache",
+        )
+        .unwrap();
+        let file_misses = IntCounter::new(
+            "file_misses_total",
+            "Symbol files which were not found in a local directory or cache",
+        )
+        .unwrap();
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time until a response was sent completely",
+            )
+            .buckets(exponential_buckets(0.001, 4.0, 10).unwrap()),
+            &["route", "method", "status"],
+        )
+        .unwrap();
+        let response_size = HistogramVec::new(
+            HistogramOpts::new("http_response_size_bytes", "Size of the response bodies")
+                .buckets(exponential_buckets(100.0, 10.0, 8).unwrap()),
+            &["route"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(downloads.clone())).unwrap();
+        registry.register(Box::new(download_bytes.clone())).unwrap();
+        registry
+            .register(Box::new(download_time_until_headers.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(download_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(download_failures.clone()))
+            .unwrap();
+        registry.register(Box::new(file_hits.clone())).unwrap();
+        registry.register(Box::new(file_misses.clone())).unwrap();
+        registry
+            .register(Box::new(request_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(response_size.clone())).unwrap();
+
+        Self {
+            registry,
+            downloads,
+            download_bytes,
+            download_time_until_headers,
+            download_duration,
+            download_failures,
+            file_hits,
+            file_misses,
+            request_duration,
+            response_size,
+        }
+    }
+}
+
+impl Metrics {
+    pub fn on_download_completed(
+        &self,
+        host: &str,
+        uncompressed_size_in_bytes: u64,
+        time_until_headers: Duration,
+        time_until_completed: Duration,
+    ) {
+        self.downloads.with_label_values(&[host]).inc();
+        self.download_bytes
+            .with_label_values(&[host])
+            .inc_by(uncompressed_size_in_bytes);
+        self.download_time_until_headers
+            .with_label_values(&[host])
+            .observe(time_until_headers.as_secs_f64());
+        self.download_duration
+            .with_label_values(&[host])
+            .observe(time_until_completed.as_secs_f64());
+    }
+
+    pub fn on_download_failed(&self, host: &str, kind: &str) {
+        self.download_failures
+            .with_label_values(&[host, kind])
+            .inc();
+    }
+
+    pub fn on_file_hit(&self) {
+        self.file_hits.inc();
+    }
+
+    pub fn on_file_missed(&self) {
+        self.file_misses.inc();
+    }
+
+    fn on_response_sent(
+        &self,
+        route: &str,
+        method: &str,
+        status: &str,
+        duration: Duration,
+        size: u64,
+    ) {
+        self.request_duration
+            .with_label_values(&[route, method, status])
+            .observe(duration.as_secs_f64());
+        self.response_size
+            .with_label_values(&[route])
+            .observe(size as f64);
+    }
+
+    /// All metrics in the Prometheus text format.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        buffer
+    }
+}
+
+/// Middleware which records the latency and response size of each request,
+/// by the route pattern that matched it.
+///
+/// Many responses are streamed, so both are recorded when the response body
+/// has been sent completely.
+pub struct RequestMetrics(pub Arc<Metrics>);
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware {
+            service: Rc::new(service),
+            metrics: Arc::clone(&self.0),
+        }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: Rc<S>,
+    metrics: Arc<Metrics>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| "unmatched".to_owned());
+        let method = req.method().to_string();
+        let service = Rc::clone(&self.service);
+        let metrics = Arc::clone(&self.metrics);
+        Box::pin(async move {
+            let response = service.call(req).await?;
+            let status = response.status().as_u16().to_string();
+            Ok(response.map_body(move |_, body| {
+                BoxBody::new(MeasuredBody {
+                    body: body.boxed(),
+                    size: 0,
+                    on_drop: Some(Box::new(move |size| {
+                        metrics.on_response_sent(&route, &method, &status, start.elapsed(), size);
+                    })),
+                })
+            }))
+        })
+    }
+}
+
+/// A response body which counts its bytes, and reports them once it's
+/// dropped, i.e. once it's been sent or the client has disconnected.
+struct MeasuredBody {
+    body: BoxBody,
+    size: u64,
+    on_drop: Option<Box<dyn FnOnce(u64)>>,
+}
+
+impl MessageBody for MeasuredBody {
+    type Error = <BoxBody as MessageBody>::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.body).poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            this.size += chunk.len() as u64;
+        }
+        poll
+    }
+}
+
+impl Drop for MeasuredBody {
+    fn drop(&mut self) {
+        if let Some(on_drop) = self.on_drop.take() {
+            on_drop(self.size);
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::configuration::SymbolSettings;
+
+/// A persistent record of the files which a symbol server didn't have.
+///
+/// Entries are keyed by server, debug name and debug ID. The file name is
+/// part of the key too, because clients probe symbol stores for several
+/// files of the same library, e.g. `xul.pdb` and the compressed `xul.pd_`.
+/// Debug IDs are stored in upper case, because they are hex digits whose
+/// case differs between clients.
+pub struct NegativeCache {
+    connection: Mutex<Connection>,
+    ttl: Duration,
+}
+
+/// A missing file on a symbol server, as requested by wholesym:
+/// `<server>/<debugName>/<debugId>/<fileName>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingFile<'a> {
+    pub server: &'a str,
+    pub debug_name: &'a str,
+    pub debug_id: &'a str,
+    pub file_name: &'a str,
+}
+
+impl NegativeCache {
+    pub fn open(db_path: &Path, ttl: Duration) -> rusqlite::Result<Self> {
+        let connection = Connection::open(db_path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS missing_files (
+                server TEXT NOT NULL,
+                debug_name TEXT NOT NULL,
+                debug_id TEXT NOT NULL,
+                file_name TEXT NOT NULL,
+                expires_at INTEGER NOT NULL,
+                PRIMARY KEY (server, debug_name, debug_id, file_name)
+            );",
+        )?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+            ttl,
+        })
+    }
+
+    /// Whether the server reported this file as missing within the TTL.
+    pub fn contains(&self, file: &MissingFile) -> rusqlite::Result<bool> {
+        let connection = self.connection.lock().unwrap();
+        let expires_at: Option<i64> = connection
+            .query_row(
+                "SELECT expires_at FROM missing_files
+                 WHERE server = ?1 AND debug_name = ?2 AND debug_id = ?3 AND file_name = ?4",
+                params![
+                    file.server,
+                    file.debug_name,
+                    file.debug_id.to_ascii_uppercase(),
+                    file.file_name
+                ],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(expires_at.is_some_and(|expires_at| expires_at > unix_time(SystemTime::now())))
+    }
+
+    pub fn insert(&self, file: &MissingFile) -> rusqlite::Result<()> {
+        let expires_at = unix_time(SystemTime::now() + self.ttl);
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT OR REPLACE INTO missing_files
+             (server, debug_name, debug_id, file_name, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                file.server,
+                file.debug_name,
+                file.debug_id.to_ascii_uppercase(),
+                file.file_name,
+                expires_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Removes the entries for a library on all servers, e.g. after its
+    /// symbols have been uploaded. Returns the number of removed entries.
+    pub fn remove(&self, debug_name: &str, debug_id: &str) -> rusqlite::Result<usize> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "DELETE FROM missing_files WHERE debug_name = ?1 AND debug_id = ?2",
+            params![debug_name, debug_id.to_ascii_uppercase()],
+        )
+    }
+
+    /// Removes all entries, and returns their number.
+    pub fn clear(&self) -> rusqlite::Result<usize> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute("DELETE FROM missing_files", [])
+    }
+
+    /// Removes the entries whose TTL has passed.
+    pub fn remove_expired(&self) -> rusqlite::Result<usize> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "DELETE FROM missing_files WHERE expires_at <= ?1",
+            params![unix_time(SystemTime::now())],
+        )
+    }
+}
+
+fn unix_time(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| i64::try_from(d.as_secs()).unwrap_or(i64::MAX))
+}
+
+/// Opens the negative cache if it's configured, and drops the entries which
+/// expired while we weren't running.
+pub fn create_negative_cache(settings: Option<&SymbolSettings>) -> Option<NegativeCache> {
+    let settings = settings?.negative_cache.as_ref()?;
+    let db_path = &settings.db_path;
+    if let Some(parent) = db_path.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            panic!("Could not create directory for negative cache database {db_path:?}: {e}");
+        }
+    }
+    let negative_cache = match NegativeCache::open(db_path, settings.ttl) {
+        Ok(negative_cache) => negative_cache,
+        Err(e) => panic!("Could not open negative cache database {db_path:?}: {e}"),
+    };
+    match negative_cache.remove_expired() {
+        Ok(count) => tracing::info!(count, "Removed expired negative cache entries"),
+        Err(e) => tracing::error!(error = %e, "Could not remove expired negative cache entries"),
+    }
+    Some(negative_cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: MissingFile = MissingFile {
+        server: "https://symbols.example.com/",
+        debug_name: "ntdll.pdb",
+        debug_id: "0C9B4E5D6D3A4B8B9E4F2A1C3D5E7F901",
+        file_name: "ntdll.pdb",
+    };
+
+    #[test]
+    fn test_insert_and_remove() {
+        let cache = NegativeCache::open(Path::new(":memory:"), Duration::from_secs(3600)).unwrap();
+        assert!(!cache.contains(&FILE).unwrap());
+        cache.insert(&FILE).unwrap();
+        assert!(cache.contains(&FILE).unwrap());
+        assert!(!cache
+            .contains(&MissingFile {
+                file_name: "ntdll.pd_",
+                ..FILE
+            })
+            .unwrap());
+        assert_eq!(cache.remove(FILE.debug_name, FILE.debug_id).unwrap(), 1);
+        assert!(!cache.contains(&FILE).unwrap());
+    }
+
+    #[test]
+    fn test_debug_ids_ignore_case() {
+        let cache = NegativeCache::open(Path::new(":memory:"), Duration::from_secs(3600)).unwrap();
+        cache
+            .insert(&MissingFile {
+                debug_id: "0c9b4e5d6d3a4b8b9e4f2a1c3d5e7f901",
+                ..FILE
+            })
+            .unwrap();
+        assert!(cache.contains(&FILE).unwrap());
+        let lowercase_id = FILE.debug_id.to_ascii_lowercase();
+        assert_eq!(cache.remove(FILE.debug_name, &lowercase_id).unwrap(), 1);
+        assert!(!cache.contains(&FILE).unwrap());
+    }
+
+    #[test]
+    fn test_entries_expire() {
+        let cache = NegativeCache::open(Path::new(":memory:"), Duration::ZERO).unwrap();
+        cache.insert(&FILE).unwrap();
+        assert!(!cache.contains(&FILE).unwrap());
+        assert_eq!(cache.remove_expired().unwrap(), 1);
+    }
+}
@@ -0,0 +1,514 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use crate::configuration::Settings;
+use crate::quota::absolute_path;
+
+/// Libraries whose files aren't evicted, e.g. the current release's
+/// `xul.pdb`.
+///
+/// The files of pinned libraries stay in the managed directories and count
+/// toward the quotas like all other files. The quota managers evict the
+/// least recently used files first and can't be told to skip files, so the
+/// quota notifiers mark the pinned files as used before each eviction. They
+/// are only evicted if they alone exceed a limit.
+///
+/// Pins come from `quota.pinned` in the configuration, or are added at
+/// runtime through `/admin/pins` and stored in `quota.pins_db_path`, so that
+/// they survive restarts.
+#[derive(Default)]
+pub struct Pins {
+    /// The quota managed directories with the symbol server layout
+    /// `<debugName>/<debugId>/<fileName>`, as absolute paths.
+    dirs: Vec<PathBuf>,
+    configured: Vec<Pin>,
+    added: RwLock<Vec<Pin>>,
+    /// The database with the added pins, without which pins can't be added.
+    db: Option<Mutex<Connection>>,
+    /// The cached files of the pinned libraries.
+    files: Mutex<Vec<PathBuf>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pin {
+    /// All libraries whose debug name matches a pattern, in which `*` matches
+    /// any number of characters and `?` matches one, e.g. `libxul*.so`.
+    DebugName(String),
+    /// A single build of a library.
+    Library {
+        debug_name: String,
+        debug_id: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinStatus {
+    pin: String,
+    /// Whether the pin is in the configuration, rather than added through
+    /// the admin API. Only the latter can be removed at runtime.
+    configured: bool,
+}
+
+/// A file of a pinned library.
+#[derive(Debug, Clone)]
+pub struct PinnedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl Pin {
+    /// Parses `<debugName>` or `<debugName>/<debugId>`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.split('/').collect::<Vec<_>>().as_slice() {
+            [debug_name] if !debug_name.is_empty() => Ok(Pin::DebugName(debug_name.to_string())),
+            [debug_name, debug_id] if !debug_name.is_empty() && !debug_id.is_empty() => {
+                Ok(Pin::Library {
+                    debug_name: debug_name.to_string(),
+                    debug_id: debug_id.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "Invalid pin {s:?}, expected <debugName> or <debugName>/<debugId>"
+            )),
+        }
+    }
+
+    fn matches_debug_name(&self, name: &str) -> bool {
+        match self {
+            Pin::DebugName(pattern) => glob_match(pattern, name),
+            Pin::Library { debug_name, .. } => debug_name.eq_ignore_ascii_case(name),
+        }
+    }
+
+    fn matches(&self, name: &str, id: &str) -> bool {
+        match self {
+            Pin::DebugName(_) => self.matches_debug_name(name),
+            Pin::Library { debug_id, .. } => {
+                self.matches_debug_name(name) && debug_id.eq_ignore_ascii_case(id)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pin::DebugName(pattern) => write!(f, "{pattern}"),
+            Pin::Library {
+                debug_name,
+                debug_id,
+            } => write!(f, "{debug_name}/{debug_id}"),
+        }
+    }
+}
+
+impl Pins {
+    /// Panics if a pin in the configuration is invalid.
+    pub fn new(settings: &Settings) -> Self {
+        let Some(quota) = &settings.quota else {
+            return Self::default();
+        };
+        let configured: Vec<Pin> = quota
+            .pinned
+            .iter()
+            .map(|pin| Pin::parse(pin).unwrap_or_else(|e| panic!("{e} in quota.pinned")))
+            .collect();
+
+        let mut dirs = Vec::new();
+        if let Some(symbols) = &settings.symbols {
+            if let Some(breakpad) = &symbols.breakpad {
+                dirs.push(breakpad.cache_dir.clone());
+                dirs.extend(breakpad.symindex_dir.clone());
+            }
+            if let Some(windows) = &symbols.windows {
+                dirs.push(windows.cache_dir.clone());
+            }
+        }
+        if let Some(upload) = &settings.upload {
+            if !upload.exempt_from_eviction {
+                dirs.push(upload.dir.clone());
+            }
+        }
+        let managed_dirs: Vec<PathBuf> = std::iter::once(&quota.managed_dir)
+            .chain(quota.caches.iter().map(|cache| &cache.dir))
+            .map(|dir| absolute_path(dir))
+            .collect();
+        let dirs = dirs
+            .into_iter()
+            .map(|dir| absolute_path(&dir))
+            .filter(|dir| managed_dirs.iter().any(|managed| dir.starts_with(managed)))
+            .collect();
+
+        let (db, added) = match &quota.pins_db_path {
+            Some(db_path) => {
+                let (connection, added) = open_db(db_path).unwrap_or_else(|e| {
+                    panic!("Could not open the pins database {db_path:?}: {e}")
+                });
+                (Some(Mutex::new(connection)), added)
+            }
+            None => (None, Vec::new()),
+        };
+        let added = added
+            .into_iter()
+            .filter(|pin| !configured.contains(pin))
+            .collect();
+
+        Self {
+            dirs,
+            configured,
+            added: RwLock::new(added),
+            db,
+            files: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Whether `quota.pins_db_path` is set, without which pins can't be
+    /// added at runtime.
+    pub fn can_add(&self) -> bool {
+        self.db.is_some()
+    }
+
+    pub fn list(&self) -> Vec<PinStatus> {
+        let configured = self.configured.iter().map(|pin| PinStatus {
+            pin: pin.to_string(),
+            configured: true,
+        });
+        let added = self.added.read().unwrap();
+        let added = added.iter().map(|pin| PinStatus {
+            pin: pin.to_string(),
+            configured: false,
+        });
+        configured.chain(added).collect()
+    }
+
+    pub fn is_configured(&self, pin: &Pin) -> bool {
+        self.configured.contains(pin)
+    }
+
+    /// Stores `pin` in the pins database. Returns false if it was already
+    /// pinned.
+    pub fn add(&self, pin: Pin) -> rusqlite::Result<bool> {
+        let Some(db) = &self.db else {
+            return Ok(false);
+        };
+        if self.configured.contains(&pin) {
+            return Ok(false);
+        }
+        let mut added = self.added.write().unwrap();
+        if added.contains(&pin) {
+            return Ok(false);
+        }
+        db.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO pins (pin) VALUES (?1)",
+            params![pin.to_string()],
+        )?;
+        added.push(pin);
+        Ok(true)
+    }
+
+    /// Removes a pin which was added at runtime from the pins database.
+    /// Returns false if it wasn't pinned that way.
+    pub fn remove(&self, pin: &Pin) -> rusqlite::Result<bool> {
+        let Some(db) = &self.db else {
+            return Ok(false);
+        };
+        let mut added = self.added.write().unwrap();
+        if !added.contains(pin) {
+            return Ok(false);
+        }
+        db.lock()
+            .unwrap()
+            .execute("DELETE FROM pins WHERE pin = ?1", params![pin.to_string()])?;
+        added.retain(|added_pin| added_pin != pin);
+        Ok(true)
+    }
+
+    fn pins(&self) -> Vec<Pin> {
+        let added = self.added.read().unwrap();
+        self.configured
+            .iter()
+            .chain(added.iter())
+            .cloned()
+            .collect()
+    }
+
+    pub fn is_pinned(&self, path: &Path) -> bool {
+        let path = absolute_path(path);
+        let Some((name, id)) = self
+            .dirs
+            .iter()
+            .find_map(|dir| debug_name_and_id(path.strip_prefix(dir).ok()?))
+        else {
+            return false;
+        };
+        self.pins().iter().any(|pin| pin.matches(name, id))
+    }
+
+    /// The cached files of the libraries which match `filter(debug_name,
+    /// debug_id)`.
+    fn files_matching(&self, filter: impl Fn(&str, &str) -> bool) -> Vec<PinnedFile> {
+        self.dirs
+            .iter()
+            .flat_map(|dir| library_files(dir, &filter))
+            .collect()
+    }
+
+    /// The cached files of all pinned libraries.
+    pub fn files(&self) -> Vec<PinnedFile> {
+        let pins = self.pins();
+        self.files_matching(|name, id| pins.iter().any(|pin| pin.matches(name, id)))
+    }
+
+    /// The number of cached files of the libraries which match `pin`.
+    pub fn file_count(&self, pin: &Pin) -> usize {
+        self.files_matching(|name, id| pin.matches(name, id)).len()
+    }
+
+    /// The number of cached files of the libraries which match `pin`, but
+    /// no other pin. After unpinning, these may be evicted again.
+    pub fn released_file_count(&self, pin: &Pin) -> usize {
+        let pins = self.pins();
+        self.files_matching(|name, id| {
+            pin.matches(name, id) && !pins.iter().any(|other| other.matches(name, id))
+        })
+        .len()
+    }
+
+    /// Looks for the cached files of the pinned libraries, after the pins
+    /// changed. Returns their number.
+    pub fn find_files(&self) -> usize {
+        let files: Vec<PathBuf> = self.files().into_iter().map(|file| file.path).collect();
+        let count = files.len();
+        *self.files.lock().unwrap() = files;
+        count
+    }
+
+    /// Remembers a new file if its library is pinned. Call this for each new
+    /// file in the managed directories.
+    pub fn on_file_created(&self, path: &Path) {
+        if self.is_pinned(path) {
+            let mut files = self.files.lock().unwrap();
+            if !files.iter().any(|file| file == path) {
+                files.push(path.to_owned());
+            }
+        }
+    }
+
+    /// The cached files of the pinned libraries, which the quota notifiers
+    /// mark as used before each eviction. Forgets the files which no longer
+    /// exist.
+    pub fn files_to_keep(&self) -> Vec<PathBuf> {
+        let mut files = self.files.lock().unwrap();
+        files.retain(|file| file.exists());
+        files.clone()
+    }
+}
+
+/// Opens the pins database, and returns the pins in it.
+fn open_db(db_path: &Path) -> rusqlite::Result<(Connection, Vec<Pin>)> {
+    if let Some(parent) = db_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            tracing::warn!(dir = ?parent, error = %e, "Could not create directory for the pins database");
+        }
+    }
+    let connection = Connection::open(db_path)?;
+    connection.execute_batch("CREATE TABLE IF NOT EXISTS pins (pin TEXT PRIMARY KEY NOT NULL);")?;
+    let pins: Vec<String> = connection
+        .prepare("SELECT pin FROM pins ORDER BY rowid")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let pins = pins
+        .iter()
+        .filter_map(|pin| match Pin::parse(pin) {
+            Ok(pin) => Some(pin),
+            Err(e) => {
+                tracing::warn!(error = e, "Skipping a pin in the pins database");
+                None
+            }
+        })
+        .collect();
+    Ok((connection, pins))
+}
+
+/// The files in `dir`, which has the symbol server layout, of the libraries
+/// for which `filter(debug_name, debug_id)` is true.
+fn library_files(dir: &Path, filter: impl Fn(&str, &str) -> bool) -> Vec<PinnedFile> {
+    let mut files = Vec::new();
+    for (name, name_dir) in subdirs(dir) {
+        for (id, id_dir) in subdirs(&name_dir) {
+            if !filter(&name, &id) {
+                continue;
+            }
+            let Ok(entries) = std::fs::read_dir(&id_dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if metadata.is_file() {
+                    files.push(PinnedFile {
+                        path: entry.path(),
+                        size: metadata.len(),
+                        modified: metadata.modified().unwrap_or_else(|_| SystemTime::now()),
+                    });
+                }
+            }
+        }
+    }
+    files
+}
+
+/// The debug name and debug ID of a file at `<debugName>/<debugId>/<fileName>`.
+fn debug_name_and_id(relative_path: &Path) -> Option<(&str, &str)> {
+    let mut components = relative_path.iter();
+    let name = components.next()?.to_str()?;
+    let id = components.next()?.to_str()?;
+    match (components.next(), components.next()) {
+        (Some(_), None) => Some((name, id)),
+        _ => None,
+    }
+}
+
+/// The subdirectories of `dir`, by name. Returns nothing if `dir` doesn't
+/// exist yet.
+fn subdirs(dir: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|entry| Some((entry.file_name().into_string().ok()?, entry.path())))
+        .collect()
+}
+
+/// Matches `name` against `pattern`, ignoring ASCII case. `*` matches any
+/// number of characters and `?` matches one.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*` if the rest doesn't match.
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c.eq_ignore_ascii_case(&name[n]) => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, start)) => {
+                    p = star + 1;
+                    n = start + 1;
+                    backtrack = Some((star, start + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("xul.pdb", "xul.pdb"));
+        assert!(glob_match("xul.pdb", "XUL.PDB"));
+        assert!(glob_match("libxul*.so", "libxul.so"));
+        assert!(glob_match("libxul*.so", "libxul-debug.so"));
+        assert!(glob_match("*.pdb", "firefox.pdb"));
+        assert!(!glob_match("n?tdll.pdb", "ntdll.pdb"));
+        assert!(glob_match("n?dll.pdb", "ntdll.pdb"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("xul.pdb", "xul.pdb2"));
+        assert!(!glob_match("*.so", "libxul.so.dbg"));
+    }
+
+    #[test]
+    fn test_parse_pin() {
+        assert_eq!(
+            Pin::parse("xul.pdb").unwrap(),
+            Pin::DebugName("xul.pdb".into())
+        );
+        let pin = Pin::parse("xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2").unwrap();
+        assert!(pin.matches("XUL.pdb", "44e4ec8c2f41492b9369d6b9a059577c2"));
+        assert!(!pin.matches("xul.pdb", "0000000000000000000000000000000000"));
+        assert_eq!(pin.to_string(), "xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2");
+        assert!(Pin::parse("").is_err());
+        assert!(Pin::parse("xul.pdb/").is_err());
+        assert!(Pin::parse("xul.pdb/ID/xul.sym").is_err());
+    }
+
+    #[test]
+    fn test_stores_pins_and_finds_their_files() {
+        let dir = std::env::temp_dir().join("reliost-test-pins");
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache_dir = dir.join("cache");
+        for name in ["xul", "ntdll"] {
+            let id_dir = cache_dir.join(format!("{name}.pdb")).join("ID");
+            std::fs::create_dir_all(&id_dir).unwrap();
+            std::fs::write(id_dir.join(format!("{name}.sym")), "MODULE").unwrap();
+        }
+        let open = || {
+            let (connection, added) = open_db(&dir.join("pins.db")).unwrap();
+            Pins {
+                dirs: vec![cache_dir.clone()],
+                configured: vec![Pin::parse("xul.pdb").unwrap()],
+                added: RwLock::new(added),
+                db: Some(Mutex::new(connection)),
+                files: Mutex::new(Vec::new()),
+            }
+        };
+
+        let pins = open();
+        assert_eq!(pins.find_files(), 1);
+        let ntdll = Pin::parse("ntdll.pdb/ID").unwrap();
+        assert_eq!(pins.add(ntdll.clone()), Ok(true));
+        assert_eq!(pins.add(ntdll.clone()), Ok(false));
+        pins.on_file_created(&cache_dir.join("ntdll.pdb/ID/ntdll.sym"));
+        assert_eq!(pins.files_to_keep().len(), 2);
+        assert_eq!(pins.file_count(&ntdll), 1);
+        assert_eq!(pins.released_file_count(&ntdll), 0);
+
+        // Added pins survive restarts.
+        drop(pins);
+        let pins = open();
+        assert_eq!(pins.list().len(), 2);
+        assert_eq!(pins.find_files(), 2);
+
+        // Evicted files are forgotten.
+        std::fs::remove_file(cache_dir.join("xul.pdb/ID/xul.sym")).unwrap();
+        assert_eq!(pins.files_to_keep().len(), 1);
+
+        assert_eq!(pins.remove(&ntdll), Ok(true));
+        assert_eq!(pins.remove(&ntdll), Ok(false));
+        assert_eq!(pins.released_file_count(&ntdll), 1);
+        drop(pins);
+        assert_eq!(open().list().len(), 1);
+    }
+
+    #[test]
+    fn test_debug_name_and_id() {
+        assert_eq!(
+            debug_name_and_id(Path::new("xul.pdb/ID/xul.sym")),
+            Some(("xul.pdb", "ID"))
+        );
+        assert_eq!(debug_name_and_id(Path::new("xul.pdb/ID")), None);
+        assert_eq!(debug_name_and_id(Path::new("a/xul.pdb/ID/xul.sym")), None);
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use wholesym::SymbolManager;
+
+use crate::downloads::{DownloadStatus, DownloadTracker};
+use crate::symbol_files::SymbolFileCache;
+use crate::symbolication::{load_symbol_map, LookupPermits, RequestModule};
+
+/// How many jobs we keep around for status requests.
+const MAX_JOBS: usize = 100;
+
+/// Downloads the symbols for lists of libraries in the background, so that
+/// they are cached before the first symbolication request needs them.
+///
+/// wholesym can only download a file by loading its symbols, which is
+/// expensive for large libraries. So libraries whose files are already cached
+/// are skipped, and the others share the fetch permits of the symbol file
+/// endpoint, as well as the lookup permits of all routes.
+pub struct Prefetcher {
+    symbol_manager: Arc<SymbolManager>,
+    lookup_permits: LookupPermits,
+    downloads: Arc<DownloadTracker>,
+    symbol_file_cache: Arc<SymbolFileCache>,
+    jobs: Mutex<Jobs>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_id: u64,
+    jobs: BTreeMap<u64, Arc<PrefetchJob>>,
+}
+
+struct PrefetchJob {
+    libraries: Vec<(RequestModule, Mutex<LibraryState>)>,
+}
+
+#[derive(Debug, Clone)]
+enum LibraryState {
+    Queued,
+    Loading,
+    Done,
+    Failed(String),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrefetchStatus {
+    id: u64,
+    queued: usize,
+    loading: usize,
+    done: usize,
+    failed: usize,
+    libraries: Vec<LibraryStatus>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryStatus {
+    debug_name: String,
+    debug_id: String,
+    code_id: Option<String>,
+    /// One of `queued`, `loading`, `done` or `failed`.
+    status: &'static str,
+    error: Option<String>,
+    /// The downloads which are running for this library right now.
+    downloads: Vec<DownloadStatus>,
+}
+
+impl Prefetcher {
+    pub fn new(
+        symbol_manager: Arc<SymbolManager>,
+        lookup_permits: LookupPermits,
+        downloads: Arc<DownloadTracker>,
+        symbol_file_cache: Arc<SymbolFileCache>,
+    ) -> Self {
+        Self {
+            symbol_manager,
+            lookup_permits,
+            downloads,
+            symbol_file_cache,
+            jobs: Mutex::new(Jobs::default()),
+        }
+    }
+
+    /// Queues the libraries for loading, and returns the ID of the new job.
+    pub fn start(&self, modules: Vec<RequestModule>) -> u64 {
+        let job = Arc::new(PrefetchJob {
+            libraries: modules
+                .into_iter()
+                .map(|module| (module, Mutex::new(LibraryState::Queued)))
+                .collect(),
+        });
+
+        let id = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let id = jobs.next_id;
+            jobs.next_id += 1;
+            jobs.jobs.insert(id, Arc::clone(&job));
+            while jobs.jobs.len() > MAX_JOBS {
+                jobs.jobs.pop_first();
+            }
+            id
+        };
+
+        tracing::info!(
+            id,
+            library_count = job.libraries.len(),
+            "Starting prefetch job"
+        );
+        for index in 0..job.libraries.len() {
+            let job = Arc::clone(&job);
+            let symbol_manager = Arc::clone(&self.symbol_manager);
+            let lookup_permits = self.lookup_permits.clone();
+            let symbol_file_cache = Arc::clone(&self.symbol_file_cache);
+            tokio::spawn(async move {
+                let (module, state) = &job.libraries[index];
+                if let Some(path) = find_cached_file(&symbol_file_cache, module).await {
+                    symbol_file_cache.report_access(&path);
+                    *state.lock().unwrap() = LibraryState::Done;
+                    return;
+                }
+                let _permit = symbol_file_cache.fetch_permit().await;
+                *state.lock().unwrap() = LibraryState::Loading;
+                // Only the download matters, so the symbol map is dropped
+                // right away.
+                let new_state =
+                    match load_symbol_map(&symbol_manager, &lookup_permits, module).await {
+                        Ok(_) => LibraryState::Done,
+                        Err(e) => {
+                            tracing::info!(
+                                debug_name = module.debug_name,
+                                message = e.message,
+                                "Could not prefetch library"
+                            );
+                            LibraryState::Failed(e.message)
+                        }
+                    };
+                *state.lock().unwrap() = new_state;
+            });
+        }
+        id
+    }
+
+    pub fn status(&self, id: u64) -> Option<PrefetchStatus> {
+        let job = Arc::clone(self.jobs.lock().unwrap().jobs.get(&id)?);
+        let mut status = PrefetchStatus {
+            id,
+            queued: 0,
+            loading: 0,
+            done: 0,
+            failed: 0,
+            libraries: Vec::with_capacity(job.libraries.len()),
+        };
+        for (module, state) in &job.libraries {
+            let state = state.lock().unwrap().clone();
+            let downloads = if matches!(state, LibraryState::Loading) {
+                self.downloads
+                    .in_flight_matching(|url| is_download_for(url, module))
+            } else {
+                Vec::new()
+            };
+            let (name, error) = match state {
+                LibraryState::Queued => {
+                    status.queued += 1;
+                    ("queued", None)
+                }
+                LibraryState::Loading => {
+                    status.loading += 1;
+                    ("loading", None)
+                }
+                LibraryState::Done => {
+                    status.done += 1;
+                    ("done", None)
+                }
+                LibraryState::Failed(message) => {
+                    status.failed += 1;
+                    ("failed", Some(message))
+                }
+            };
+            status.libraries.push(LibraryStatus {
+                debug_name: module.debug_name.clone(),
+                debug_id: module.debug_id.breakpad().to_string(),
+                code_id: module.code_id.as_ref().map(ToString::to_string),
+                status: name,
+                error,
+                downloads,
+            });
+        }
+        Some(status)
+    }
+}
+
+/// Returns the cached Breakpad or PDB file of `module`, if we have one.
+/// Libraries which are only on debuginfod servers are always loaded.
+async fn find_cached_file(
+    symbol_file_cache: &SymbolFileCache,
+    module: &RequestModule,
+) -> Option<PathBuf> {
+    let debug_name = &module.debug_name;
+    let debug_id = module.debug_id.breakpad().to_string();
+    let stem = debug_name.strip_suffix(".pdb").unwrap_or(debug_name);
+    let sym_file_name = format!("{stem}.sym");
+    for file_name in [sym_file_name.as_str(), debug_name] {
+        if let Some(path) = symbol_file_cache
+            .find(debug_name, &debug_id, file_name)
+            .await
+        {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Whether the symbol manager downloads `url` for `module`. Symbol servers
+/// use the layout `<debugName>/<debugId>/<fileName>`, and debuginfod servers
+/// use `buildid/<codeId>/debuginfo`.
+fn is_download_for(url: &str, module: &RequestModule) -> bool {
+    let symbol_server_path = format!("/{}/{}/", module.debug_name, module.debug_id.breakpad());
+    if url.contains(&symbol_server_path) {
+        return true;
+    }
+    module
+        .code_id
+        .as_ref()
+        .is_some_and(|code_id| url.contains(&format!("/buildid/{code_id}/")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_download_for() {
+        let module = RequestModule::try_from((
+            "xul.pdb".to_string(),
+            "44E4EC8C2F41492B9369D6B9A059577C2".to_string(),
+        ))
+        .unwrap();
+        assert!(is_download_for(
+            "https://symbols.mozilla.org/try/xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2/xul.sym",
+            &module
+        ));
+        assert!(!is_download_for(
+            "https://symbols.mozilla.org/try/ntdll.pdb/44E4EC8C2F41492B9369D6B9A059577C2/ntdll.sym",
+            &module
+        ));
+    }
+}
@@ -0,0 +1,687 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use samply_quota_manager::{QuotaManager, QuotaManagerNotifier};
+use tokio::sync::Notify;
+
+use crate::configuration::{CacheQuotaSettings, MinFreeSpace, Settings};
+use crate::pins::Pins;
+use crate::quota_db::{unix_seconds, QuotaDatabase};
+
+/// How often we enforce the quotas, in addition to after new files.
+pub const QUOTA_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The minimum time between two quota checks, so that the files which are
+/// created in the meantime are handled by one check.
+pub const QUOTA_CHECK_MIN_DELAY: Duration = Duration::from_secs(1);
+
+/// The heartbeat fails if no quota check has finished for this long.
+const QUOTA_CHECK_STALE_AFTER: Duration = Duration::from_secs(3 * 60);
+
+/// A directory with its own quota manager, and its own size and age limits.
+struct ManagedCache {
+    /// The managed directory, as an absolute path.
+    dir: PathBuf,
+    db_path: PathBuf,
+    size_limit: Option<u64>,
+    min_free_space: Option<MinFreeSpace>,
+    quota_manager: QuotaManager,
+    /// Whether the database is new, so that the files which are already in
+    /// the directory need to be added to the inventory.
+    needs_rescan: bool,
+}
+
+/// The quota managers for `[quota]` and for each of `[[quota.caches]]`.
+#[derive(Default)]
+pub struct QuotaManagers {
+    caches: Vec<ManagedCache>,
+    pins: Arc<Pins>,
+    health: Arc<QuotaHealth>,
+}
+
+/// What `/__heartbeat__` reports about the quota managers.
+#[derive(Debug)]
+pub struct QuotaHealth {
+    /// The quota managers which couldn't be started, by the path of their
+    /// database, with the reason. Their directories grow without bound.
+    failures: Vec<(PathBuf, String)>,
+    /// When the last quota check finished, and its result. Starts out as
+    /// the time at which the quota managers were created.
+    last_check: Mutex<(Instant, Result<(), String>)>,
+}
+
+impl Default for QuotaHealth {
+    fn default() -> Self {
+        Self {
+            failures: Vec::new(),
+            last_check: Mutex::new((Instant::now(), Ok(()))),
+        }
+    }
+}
+
+impl QuotaHealth {
+    /// Whether the quota manager with the database at `db_path` is running,
+    /// and its limits are being enforced.
+    pub fn check(&self, db_path: &Path) -> Result<(), String> {
+        if let Some((_, error)) = self.failures.iter().find(|(failed, _)| failed == db_path) {
+            return Err(format!("The quota manager isn't running: {error}"));
+        }
+        let (checked_at, result) = &*self.last_check.lock().unwrap();
+        let elapsed = checked_at.elapsed();
+        if elapsed > QUOTA_CHECK_STALE_AFTER {
+            return Err(format!(
+                "No quota check has finished for {} seconds",
+                elapsed.as_secs()
+            ));
+        }
+        result
+            .clone()
+            .map_err(|e| format!("The last quota check failed: {e}"))
+    }
+
+    fn record_check(&self, result: Result<(), String>) {
+        *self.last_check.lock().unwrap() = (Instant::now(), result);
+    }
+}
+
+/// Sends each notification to the quota manager of the file's directory.
+///
+/// If managed directories are nested, e.g. a Windows cache with its own
+/// budget inside the managed directory of `[quota]`, the innermost directory
+/// wins, so that each file is counted by exactly one quota manager.
+///
+/// The files of pinned libraries are marked as used before each eviction,
+/// so that all other files are evicted first.
+#[derive(Clone)]
+pub struct QuotaNotifiers {
+    /// Innermost directories first.
+    notifiers: Arc<[ManagedNotifier]>,
+    pins: Arc<Pins>,
+    health: Arc<QuotaHealth>,
+    file_created: Arc<Notify>,
+}
+
+struct ManagedNotifier {
+    dir: PathBuf,
+    db_path: PathBuf,
+    size_limit: Option<u64>,
+    min_free_space: Option<MinFreeSpace>,
+    notifier: QuotaManagerNotifier,
+    /// The total size of the files, and when it was read from the database.
+    /// New files are added to it in between.
+    usage: Mutex<Option<(u64, Instant)>>,
+    /// Whether the size limit is below `size_limit` to keep free space.
+    lowered_size_limit: AtomicBool,
+}
+
+impl QuotaManagers {
+    /// Returns `None` if there are no quota managers.
+    pub fn notifiers(&self) -> Option<QuotaNotifiers> {
+        if self.caches.is_empty() {
+            return None;
+        }
+        let mut notifiers: Vec<ManagedNotifier> = self
+            .caches
+            .iter()
+            .map(|cache| ManagedNotifier {
+                dir: cache.dir.clone(),
+                db_path: cache.db_path.clone(),
+                size_limit: cache.size_limit,
+                min_free_space: cache.min_free_space,
+                notifier: cache.quota_manager.notifier(),
+                usage: Mutex::new(None),
+                lowered_size_limit: AtomicBool::new(false),
+            })
+            .collect();
+        notifiers.sort_by_key(|managed| std::cmp::Reverse(managed.dir.components().count()));
+        Some(QuotaNotifiers {
+            notifiers: notifiers.into(),
+            pins: Arc::clone(&self.pins),
+            health: Arc::clone(&self.health),
+            file_created: Arc::new(Notify::new()),
+        })
+    }
+
+    pub fn health(&self) -> Arc<QuotaHealth> {
+        Arc::clone(&self.health)
+    }
+
+    /// Shuts down the file deletion threads.
+    pub async fn finish(self) {
+        for cache in self.caches {
+            cache.quota_manager.finish().await;
+        }
+    }
+}
+
+impl QuotaNotifiers {
+    fn managed_notifier_for(&self, path: &Path) -> Option<&ManagedNotifier> {
+        let path = absolute_path(path);
+        self.notifiers
+            .iter()
+            .find(|managed| path.starts_with(&managed.dir))
+    }
+
+    pub fn pins(&self) -> &Pins {
+        &self.pins
+    }
+
+    pub fn on_file_created(&self, path: &Path, size_in_bytes: u64, creation_time: SystemTime) {
+        if let Some(managed) = self.managed_notifier_for(path) {
+            managed
+                .notifier
+                .on_file_created(path, size_in_bytes, creation_time);
+            if let Some((usage, _)) = managed.usage.lock().unwrap().as_mut() {
+                *usage += size_in_bytes;
+            }
+            self.pins.on_file_created(path);
+            self.file_created.notify_one();
+        }
+    }
+
+    pub fn on_file_accessed(&self, path: &Path, access_time: SystemTime) {
+        if let Some(managed) = self.managed_notifier_for(path) {
+            managed.notifier.on_file_accessed(path, access_time);
+        }
+    }
+
+    /// Enforces the size and age limits of all quota managers.
+    pub fn trigger_eviction_if_needed(&self) {
+        self.mark_pinned_files_used();
+        for managed in self.notifiers.iter() {
+            managed.notifier.trigger_eviction_if_needed();
+        }
+    }
+
+    /// Enforces the quotas, including the free space, and records the
+    /// result for the heartbeat. A check which hangs or panics is noticed
+    /// because it isn't recorded.
+    pub fn check_quotas(&self) {
+        self.mark_pinned_files_used();
+        let result = self.enforce_free_space();
+        if let Err(e) = &result {
+            tracing::warn!(error = e, "Could not check the free disk space");
+        }
+        for managed in self.notifiers.iter() {
+            managed.notifier.trigger_eviction_if_needed();
+        }
+        self.health.record_check(result);
+    }
+
+    /// Makes the files of pinned libraries the most recently used ones, so
+    /// that the quota managers evict them last, and never for their age.
+    fn mark_pinned_files_used(&self) {
+        let now = SystemTime::now();
+        for path in self.pins.files_to_keep() {
+            self.on_file_accessed(&path, now);
+        }
+    }
+
+    /// Waits until a file is created, or returns immediately if one was
+    /// created since the last call.
+    pub async fn file_created(&self) {
+        self.file_created.notified().await
+    }
+
+    /// Lowers the size limit of each quota manager with a `min_free_space`
+    /// while its file system has less free space than that, so that files
+    /// are evicted, and restores `size_limit` once there's enough again.
+    ///
+    /// Quota managers whose directories are on the same file system share
+    /// its free space, so the shortfall below the minimum is split between
+    /// them.
+    fn enforce_free_space(&self) -> Result<(), String> {
+        // The quota managers on each file system, with their minimum free
+        // space.
+        type Managers<'a> = Vec<(&'a ManagedNotifier, u64)>;
+        let mut file_systems: Vec<(u64, fs4::FsStats, Managers)> = Vec::new();
+        for managed in self.notifiers.iter() {
+            let Some(min_free_space) = managed.min_free_space else {
+                continue;
+            };
+            let file_system_id = file_system_id(&managed.dir)
+                .map_err(|e| format!("Could not get the file system of {:?}: {e}", managed.dir))?;
+            let stats = fs4::statvfs(&managed.dir)
+                .map_err(|e| format!("Could not get the free space for {:?}: {e}", managed.dir))?;
+            let min_free_space = min_free_space.bytes(stats.total_space());
+            match file_systems
+                .iter_mut()
+                .find(|(id, _, _)| *id == file_system_id)
+            {
+                Some((_, _, managers)) => managers.push((managed, min_free_space)),
+                None => file_systems.push((file_system_id, stats, vec![(managed, min_free_space)])),
+            }
+        }
+
+        for (_, stats, managers) in file_systems {
+            let available = stats.available_space();
+            let min_free_space = managers
+                .iter()
+                .map(|(_, min_free_space)| *min_free_space)
+                .max()
+                .unwrap_or(0);
+            if available >= min_free_space {
+                for (managed, _) in managers {
+                    managed.restore_size_limit();
+                }
+                continue;
+            }
+            tracing::warn!(
+                dirs = ?managers.iter().map(|(managed, _)| &managed.dir).collect::<Vec<_>>(),
+                available,
+                min_free_space,
+                "Low on disk space, evicting files"
+            );
+            let usages = managers
+                .iter()
+                .map(|(managed, _)| managed.usage())
+                .collect::<Result<Vec<u64>, String>>()?;
+            let size_limits = split_shortfall(&usages, min_free_space - available);
+            for ((managed, _), size_limit) in managers.into_iter().zip(size_limits) {
+                managed.lower_size_limit(size_limit);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the files in `dir` to the inventory of its quota manager, after
+    /// its database was lost. Skips the files in `exempt_dirs` and in nested
+    /// directories with their own quota manager. Returns the number of files
+    /// which were added to the inventory.
+    fn rescan(&self, dir: &Path, exempt_dirs: &[PathBuf]) -> usize {
+        let mut added = 0;
+        let mut dirs = vec![dir.to_owned()];
+        while let Some(current_dir) = dirs.pop() {
+            if exempt_dirs
+                .iter()
+                .any(|exempt| current_dir.starts_with(exempt))
+            {
+                continue;
+            }
+            let entries = match std::fs::read_dir(&current_dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    tracing::warn!(dir = ?current_dir, error = %e, "Could not scan directory");
+                    continue;
+                }
+            };
+            for entry in entries.flatten() {
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                let path = entry.path();
+                if file_type.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                if !file_type.is_file() {
+                    continue;
+                }
+                // Files in nested directories with their own quota manager.
+                let Some(managed) = self
+                    .managed_notifier_for(&path)
+                    .filter(|managed| managed.dir == dir)
+                else {
+                    continue;
+                };
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                let modified = metadata.modified().unwrap_or_else(|_| SystemTime::now());
+                managed
+                    .notifier
+                    .on_file_created(&path, metadata.len(), modified);
+                added += 1;
+            }
+        }
+        added
+    }
+}
+
+impl ManagedNotifier {
+    /// The total size of the files. It's read from the database at most
+    /// once per [`QUOTA_CHECK_INTERVAL`].
+    fn usage(&self) -> Result<u64, String> {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some((usage, read_at)) = *usage {
+            if read_at.elapsed() < QUOTA_CHECK_INTERVAL {
+                return Ok(usage);
+            }
+        }
+        // samply-quota-manager doesn't report its usage, so we read its
+        // private schema. That's only safe because its version is pinned to
+        // `=0.1.0` in Cargo.toml, see `QuotaDatabase`.
+        let total_size = QuotaDatabase::open(&self.dir, &self.db_path)?
+            .total_size()
+            .map_err(|e| e.to_string())?;
+        *usage = Some((total_size, Instant::now()));
+        Ok(total_size)
+    }
+
+    /// Evicts files until they take at most `size_limit` bytes, or less if
+    /// `self.size_limit` is lower.
+    fn lower_size_limit(&self, size_limit: u64) {
+        let size_limit = self
+            .size_limit
+            .map_or(size_limit, |configured| configured.min(size_limit));
+        self.lowered_size_limit.store(true, Ordering::Relaxed);
+        self.notifier.set_max_total_size(Some(size_limit));
+        self.notifier.trigger_eviction_if_needed();
+        if let Some((usage, _)) = self.usage.lock().unwrap().as_mut() {
+            *usage = (*usage).min(size_limit);
+        }
+    }
+
+    /// Goes back to `self.size_limit` after [`Self::lower_size_limit`].
+    fn restore_size_limit(&self) {
+        if self.lowered_size_limit.swap(false, Ordering::Relaxed) {
+            tracing::info!(dir = ?self.dir, "Enough disk space again, restoring the size limit");
+            self.notifier.set_max_total_size(self.size_limit);
+        }
+    }
+}
+
+pub fn create_quota_managers(settings: &Settings, pins: Arc<Pins>) -> QuotaManagers {
+    let Some(quota) = settings.quota.as_ref() else {
+        return QuotaManagers::default();
+    };
+    let managed_dir = CacheQuotaSettings {
+        dir: quota.managed_dir.clone(),
+        db_path: quota.db_path.clone(),
+        size_limit: quota.size_limit,
+        age_limit: quota.age_limit,
+        min_free_space: quota.min_free_space,
+    };
+    let all_settings: Vec<&CacheQuotaSettings> =
+        std::iter::once(&managed_dir).chain(&quota.caches).collect();
+    let mut caches = Vec::new();
+    let mut failures = Vec::new();
+    for settings in &all_settings {
+        let dir = absolute_path(&settings.dir);
+        let nested_dirs: Vec<PathBuf> = all_settings
+            .iter()
+            .map(|other| absolute_path(&other.dir))
+            .filter(|other_dir| other_dir != &dir && other_dir.starts_with(&dir))
+            .collect();
+        match create_managed_cache(settings, &nested_dirs) {
+            Ok(cache) => caches.push(cache),
+            Err(error) => {
+                // Restarting wouldn't help, so we serve requests without
+                // this quota manager, and the heartbeat fails.
+                tracing::error!(
+                    dir = ?settings.dir,
+                    db_path = ?settings.db_path,
+                    error,
+                    "Could not start the quota manager, its files won't be evicted"
+                );
+                failures.push((settings.db_path.clone(), error));
+            }
+        }
+    }
+
+    if let Some(upload) = settings.upload.as_ref() {
+        let upload_dir = absolute_path(&upload.dir);
+        if upload.exempt_from_eviction && caches.iter().any(|c| upload_dir.starts_with(&c.dir)) {
+            tracing::warn!(
+                "The upload directory {:?} is inside a quota managed directory. Uploaded files are exempt from eviction, so move it elsewhere to keep them out of the quota.",
+                upload.dir
+            );
+        }
+    }
+
+    let quota_managers = QuotaManagers {
+        caches,
+        pins,
+        health: Arc::new(QuotaHealth {
+            failures,
+            ..QuotaHealth::default()
+        }),
+    };
+    if let Some(notifiers) = quota_managers.notifiers() {
+        let exempt_dirs: Vec<PathBuf> = settings
+            .upload
+            .iter()
+            .filter(|upload| upload.exempt_from_eviction)
+            .map(|upload| absolute_path(&upload.dir))
+            .collect();
+        let rescan_dirs: Vec<PathBuf> = quota_managers
+            .caches
+            .iter()
+            .filter(|c| c.needs_rescan)
+            .map(|c| c.dir.clone())
+            .collect();
+        // Walking large caches takes a while, so the server starts without
+        // waiting for it.
+        tokio::task::spawn_blocking(move || {
+            for dir in rescan_dirs {
+                let added = notifiers.rescan(&dir, &exempt_dirs);
+                tracing::info!(?dir, added, "Rebuilt the quota inventory");
+            }
+            // Files which were cached before their library was pinned.
+            let count = notifiers.pins().find_files();
+            tracing::info!(count, "Found the files of pinned libraries");
+        });
+    }
+    quota_managers
+}
+
+/// Creates the quota manager for one directory. `nested_dirs` are the
+/// directories inside it which have their own quota manager.
+fn create_managed_cache(
+    settings: &CacheQuotaSettings,
+    nested_dirs: &[PathBuf],
+) -> Result<ManagedCache, String> {
+    let CacheQuotaSettings {
+        dir: managed_dir,
+        db_path,
+        size_limit,
+        age_limit,
+        min_free_space,
+    } = settings.clone();
+
+    std::fs::create_dir_all(&managed_dir)
+        .map_err(|e| format!("Could not create quota managed directory {managed_dir:?}: {e}"))?;
+
+    if lists_files_in(&managed_dir, &db_path, nested_dirs) {
+        // A `[[quota.caches]]` entry was added for a directory whose files
+        // are in this inventory. They'd be counted twice, and we can't take
+        // them out of the quota manager's database, so we start over.
+        tracing::info!(
+            ?db_path,
+            "The quota database lists files of a nested cache, rebuilding it"
+        );
+        remove_db(&db_path)?;
+    }
+
+    if let Some(db_dir) = db_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(db_dir).map_err(|e| {
+            format!("Could not create the directory of the quota database {db_path:?}: {e}")
+        })?;
+    }
+
+    let mut needs_rescan = !db_path.exists();
+    let quota_manager = match QuotaManager::new(&managed_dir, &db_path) {
+        Ok(quota_manager) => quota_manager,
+        Err(e) if !db_path.exists() => {
+            return Err(format!(
+                "Could not create QuotaManager with symbol cache database {db_path:?}: {e}"
+            ));
+        }
+        Err(e) => {
+            // The database is probably corrupt, e.g. after a crash. Keep it
+            // for inspection and start over with a fresh one.
+            let moved_to = move_aside(&db_path)?;
+            tracing::error!(
+                error = %e,
+                ?db_path,
+                ?moved_to,
+                "Could not open the quota database, moved it aside"
+            );
+            needs_rescan = true;
+            QuotaManager::new(&managed_dir, &db_path).map_err(|e| {
+                format!("Could not create QuotaManager with symbol cache database {db_path:?}: {e}")
+            })?
+        }
+    };
+
+    quota_manager.set_max_total_size(size_limit);
+    quota_manager.set_max_age(age_limit.map(|d| d.as_secs()));
+    Ok(ManagedCache {
+        dir: absolute_path(&managed_dir),
+        db_path,
+        size_limit,
+        min_free_space,
+        quota_manager,
+        needs_rescan,
+    })
+}
+
+/// Whether the inventory at `db_path` has files in any of `nested_dirs`.
+fn lists_files_in(managed_dir: &Path, db_path: &Path, nested_dirs: &[PathBuf]) -> bool {
+    if nested_dirs.is_empty() || !db_path.exists() {
+        return false;
+    }
+    let files = QuotaDatabase::open(&absolute_path(managed_dir), db_path)
+        .and_then(|db| db.files().map_err(|e| e.to_string()));
+    match files {
+        Ok(files) => files.iter().any(|file| {
+            let path = absolute_path(&file.path);
+            nested_dirs.iter().any(|dir| path.starts_with(dir))
+        }),
+        // Broken databases are moved aside by the caller.
+        Err(_) => false,
+    }
+}
+
+/// Deletes an SQLite database and its journal files. Fails if the database
+/// can't be deleted, because the quota manager would keep using it.
+fn remove_db(db_path: &Path) -> Result<(), String> {
+    std::fs::remove_file(db_path)
+        .map_err(|e| format!("Could not delete the quota database {db_path:?}: {e}"))?;
+    for journal_suffix in ["-journal", "-wal", "-shm"] {
+        let mut journal = db_path.as_os_str().to_owned();
+        journal.push(journal_suffix);
+        let _ = std::fs::remove_file(journal);
+    }
+    Ok(())
+}
+
+/// Renames a broken SQLite database, and its journal files, to
+/// `<name>.corrupt-<timestamp>`. Returns the new path, or fails if the
+/// database can't be moved, because we couldn't create a fresh one either.
+fn move_aside(db_path: &Path) -> Result<PathBuf, String> {
+    let timestamp = unix_seconds(SystemTime::now());
+    let with_suffix = |path: &Path, suffix: &str| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+    let moved_to = with_suffix(db_path, &format!(".corrupt-{timestamp}"));
+    std::fs::rename(db_path, &moved_to).map_err(|e| {
+        format!("Could not move the quota database {db_path:?} to {moved_to:?}: {e}")
+    })?;
+    for journal_suffix in ["-journal", "-wal", "-shm"] {
+        let journal = with_suffix(db_path, journal_suffix);
+        if journal.exists() {
+            let _ = std::fs::rename(&journal, with_suffix(&moved_to, journal_suffix));
+        }
+    }
+    Ok(moved_to)
+}
+
+/// The size limits which free `shortfall` bytes on a file system, for the
+/// quota managers on it whose files take `usages` bytes.
+///
+/// The shortfall is split in proportion to the usage, because caches can
+/// only shrink by as much as they have.
+fn split_shortfall(usages: &[u64], shortfall: u64) -> Vec<u64> {
+    let shortfall = u128::from(shortfall);
+    let total_usage: u128 = usages.iter().map(|&usage| u128::from(usage)).sum();
+    usages
+        .iter()
+        .map(|&usage| {
+            if total_usage == 0 {
+                return 0;
+            }
+            let share = shortfall * u128::from(usage) / total_usage;
+            usage.saturating_sub(u64::try_from(share).unwrap_or(u64::MAX))
+        })
+        .collect()
+}
+
+/// Identifies the file system of `dir`, so that quota managers on the same
+/// file system can share its free space.
+#[cfg(unix)]
+fn file_system_id(dir: &Path) -> std::io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(std::fs::metadata(dir)?.dev())
+}
+
+/// Identifies the file system of `dir` by its drive or UNC share, which
+/// misses volumes that are mounted into folders.
+#[cfg(not(unix))]
+fn file_system_id(dir: &Path) -> std::io::Result<u64> {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    absolute_path(dir).components().next().hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+/// Makes relative paths absolute, without resolving symlinks, so that paths
+/// from the configuration and paths from wholesym can be compared.
+pub fn absolute_path(path: &Path) -> PathBuf {
+    std::path::absolute(path)
+        .unwrap_or_else(|_| path.to_owned())
+        .components()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_absolute_path() {
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            absolute_path(Path::new("./cache/symbols/windows/")),
+            cwd.join("cache").join("symbols").join("windows")
+        );
+    }
+
+    #[test]
+    fn test_quota_health() {
+        let health = QuotaHealth {
+            failures: vec![("broken.db".into(), "Read-only file system".to_owned())],
+            ..QuotaHealth::default()
+        };
+        assert!(health
+            .check(Path::new("broken.db"))
+            .unwrap_err()
+            .contains("Read-only file system"));
+        assert_eq!(health.check(Path::new("symbols.db")), Ok(()));
+
+        health.record_check(Err("No such file or directory".to_owned()));
+        assert!(health.check(Path::new("symbols.db")).is_err());
+        health.record_check(Ok(()));
+        assert_eq!(health.check(Path::new("symbols.db")), Ok(()));
+
+        // The checks stopped.
+        if let Some(long_ago) = Instant::now().checked_sub(2 * QUOTA_CHECK_STALE_AFTER) {
+            health.last_check.lock().unwrap().0 = long_ago;
+            assert!(health
+                .check(Path::new("symbols.db"))
+                .unwrap_err()
+                .contains("No quota check has finished"));
+        }
+    }
+
+    #[test]
+    fn test_split_shortfall() {
+        assert_eq!(split_shortfall(&[500], 200), vec![300]);
+        assert_eq!(split_shortfall(&[1000, 3000], 1000), vec![750, 2250]);
+        assert_eq!(split_shortfall(&[500, 0], 2000), vec![0, 0]);
+    }
+}
@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, OpenFlags};
+
+/// How long we wait for the quota manager to release its lock on the
+/// database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Read-only access to the file inventory in the database of a
+/// [`samply_quota_manager::QuotaManager`], which doesn't expose its inventory
+/// through its API.
+///
+/// This relies on the schema of samply-quota-manager 0.1.0, which is why that
+/// version is pinned in Cargo.toml:
+///
+/// ```sql
+/// CREATE TABLE files (
+///     path TEXT PRIMARY KEY,
+///     size INTEGER NOT NULL,
+///     creation_time INTEGER NOT NULL,
+///     last_access_time INTEGER NOT NULL
+/// );
+/// ```
+///
+/// The times are in milliseconds since the Unix epoch, and the paths are
+/// relative to the managed directory or absolute.
+pub struct QuotaDatabase {
+    connection: Connection,
+    managed_dir: PathBuf,
+}
+
+/// A file in the inventory.
+#[derive(Debug, Clone)]
+pub struct InventoryFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub last_access: Option<SystemTime>,
+}
+
+impl QuotaDatabase {
+    pub fn open(managed_dir: &Path, db_path: &Path) -> Result<Self, String> {
+        let connection = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("Could not open {db_path:?}: {e}"))?;
+        connection
+            .busy_timeout(BUSY_TIMEOUT)
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            connection,
+            managed_dir: managed_dir.to_owned(),
+        })
+    }
+
+    pub fn files(&self) -> rusqlite::Result<Vec<InventoryFile>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT path, size, last_access_time FROM files")?;
+        let files = statement
+            .query_map([], |row| {
+                let path: String = row.get(0)?;
+                let size: i64 = row.get(1)?;
+                let last_access: i64 = row.get(2)?;
+                Ok(InventoryFile {
+                    // Joining an absolute path returns it unchanged.
+                    path: self.managed_dir.join(path),
+                    size: u64::try_from(size).unwrap_or(0),
+                    last_access: Some(system_time_from_millis(last_access)),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(files)
+    }
+
+    /// The total size of the files in the inventory.
+    pub fn total_size(&self) -> rusqlite::Result<u64> {
+        let total_size: Option<i64> =
+            self.connection
+                .query_row("SELECT SUM(size) FROM files", [], |row| row.get(0))?;
+        Ok(total_size.map_or(0, |size| u64::try_from(size).unwrap_or(0)))
+    }
+}
+
+fn system_time_from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(u64::try_from(millis).unwrap_or(0))
+}
+
+/// Seconds since the Unix epoch, for JSON responses.
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use samply_quota_manager::QuotaManager;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reads_the_quota_manager_inventory() {
+        let dir = std::env::temp_dir().join("reliost-test-quota-db");
+        let _ = std::fs::remove_dir_all(&dir);
+        let managed_dir = dir.join("cache");
+        std::fs::create_dir_all(managed_dir.join("xul.pdb/ID")).unwrap();
+        let file_path = managed_dir.join("xul.pdb/ID/xul.sym");
+        std::fs::write(&file_path, [0; 1000]).unwrap();
+        let db_path = dir.join("inventory.db");
+        let last_access = UNIX_EPOCH + Duration::from_millis(1_700_000_001_000);
+
+        let quota_manager = QuotaManager::new(&managed_dir, &db_path).unwrap();
+        let notifier = quota_manager.notifier();
+        notifier.on_file_created(
+            &file_path,
+            1000,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        );
+        notifier.on_file_accessed(&file_path, last_access);
+        quota_manager.finish().await;
+
+        let db = QuotaDatabase::open(&managed_dir, &db_path).unwrap();
+        let files = db.files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, file_path);
+        assert_eq!(files[0].size, 1000);
+        assert_eq!(db.total_size().unwrap(), 1000);
+        assert_eq!(files[0].last_access, Some(last_access));
+    }
+}
@@ -0,0 +1,187 @@
+use std::io::{self, BufReader, Read};
+
+use actix_web::{
+    http::header::{self, ContentEncoding},
+    web, HttpRequest,
+};
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tokio_util::io::{StreamReader, SyncIoBridge};
+
+use crate::error::ApiError;
+
+/// The default maximum size of a request body, in bytes, if none is
+/// configured in `[server]`.
+pub const DEFAULT_MAX_REQUEST_BODY_SIZE: usize = 100 * 1000 * 1000; // 100 MB
+
+/// How many body chunks can be in flight between the payload stream and the
+/// blocking reader.
+const CHUNK_CHANNEL_CAPACITY: usize = 4;
+
+const BROTLI_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The maximum size of a request body, in bytes. Stored as app data.
+///
+/// For compressed bodies, the limit applies both to the compressed and to the
+/// decompressed size, which protects us from decompression bombs.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestBodyLimit(pub usize);
+
+/// Returns the encoding of the request body, based on its `Content-Encoding`
+/// header.
+pub fn request_encoding(req: &HttpRequest) -> Result<ContentEncoding, ApiError> {
+    let Some(value) = req.headers().get(header::CONTENT_ENCODING) else {
+        return Ok(ContentEncoding::Identity);
+    };
+    let value = value.to_str().unwrap_or_default();
+    match value.parse() {
+        Ok(
+            encoding @ (ContentEncoding::Identity
+            | ContentEncoding::Gzip
+            | ContentEncoding::Deflate
+            | ContentEncoding::Zstd
+            | ContentEncoding::Brotli),
+        ) => Ok(encoding),
+        _ => Err(ApiError::UnsupportedContentEncoding(value.to_owned())),
+    }
+}
+
+/// Reads and decompresses the entire request body into memory.
+pub async fn read_body(
+    payload: web::Payload,
+    encoding: ContentEncoding,
+    limit: usize,
+) -> Result<Bytes, ApiError> {
+    process_body(payload, encoding, limit, |reader| {
+        let mut body = Vec::new();
+        reader
+            .read_to_end(&mut body)
+            .map_err(ApiError::InvalidContentEncoding)?;
+        Ok(Bytes::from(body))
+    })
+    .await
+}
+
+/// Parses the request body as JSON while it's being received, without
+/// buffering the entire body in memory.
+pub async fn parse_json_body<T>(
+    payload: web::Payload,
+    encoding: ContentEncoding,
+    limit: usize,
+) -> Result<T, ApiError>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    process_body(payload, encoding, limit, |reader| {
+        // Reading through the bridge one byte at a time would be slow.
+        serde_json::from_reader(BufReader::new(reader)).map_err(ApiError::InvalidRequest)
+    })
+    .await
+}
+
+/// Runs `f` on a blocking task with a reader for the decompressed request
+/// body.
+///
+/// The payload stream is not `Send`, so its chunks are forwarded through a
+/// bounded channel to the blocking task.
+pub async fn process_body<T, F>(
+    mut payload: web::Payload,
+    encoding: ContentEncoding,
+    limit: usize,
+    f: F,
+) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut dyn Read) -> Result<T, ApiError> + Send + 'static,
+{
+    let (chunk_tx, chunk_rx) = mpsc::channel::<Result<Bytes, io::Error>>(CHUNK_CHANNEL_CAPACITY);
+    let processor = tokio::task::spawn_blocking(move || {
+        let reader = SyncIoBridge::new(StreamReader::new(ReceiverStream::new(chunk_rx)));
+        let reader = decoding_reader(reader, encoding).map_err(ApiError::InvalidContentEncoding)?;
+        let mut reader = LimitedReader::new(reader, limit);
+        let result = f(&mut reader);
+        if reader.limit_exceeded() {
+            return Err(ApiError::PayloadTooLarge { limit });
+        }
+        result
+    });
+
+    let forward_result = async move {
+        let mut received_len = 0;
+        while let Some(chunk) = payload.next().await {
+            let chunk = chunk?;
+            received_len += chunk.len();
+            if received_len > limit {
+                // Make the reader fail so that it stops waiting for more data.
+                let _ = chunk_tx
+                    .send(Err(io::Error::other("Request body too large")))
+                    .await;
+                return Err(ApiError::PayloadTooLarge { limit });
+            }
+            if chunk_tx.send(Ok(chunk)).await.is_err() {
+                // The processor has stopped early, most likely because of a
+                // syntax error. Its result will tell us what went wrong.
+                break;
+            }
+        }
+        Ok::<(), ApiError>(())
+    }
+    .await;
+
+    let process_result = processor
+        .await
+        .map_err(|e| ApiError::Internal(format!("Request body task failed: {e}")))?;
+    forward_result?;
+    process_result
+}
+
+fn decoding_reader<'a, R: Read + 'a>(
+    reader: R,
+    encoding: ContentEncoding,
+) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(match encoding {
+        ContentEncoding::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+        ContentEncoding::Deflate => Box::new(flate2::read::ZlibDecoder::new(reader)),
+        ContentEncoding::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        ContentEncoding::Brotli => Box::new(brotli::Decompressor::new(reader, BROTLI_BUFFER_SIZE)),
+        _ => Box::new(reader),
+    })
+}
+
+/// A reader which fails once more than `limit` bytes have been read from it.
+struct LimitedReader<R> {
+    inner: R,
+    remaining: usize,
+    limit_exceeded: bool,
+}
+
+impl<R: Read> LimitedReader<R> {
+    fn new(inner: R, limit: usize) -> Self {
+        Self {
+            inner,
+            remaining: limit,
+            limit_exceeded: false,
+        }
+    }
+
+    fn limit_exceeded(&self) -> bool {
+        self.limit_exceeded
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Allow reading one byte past the limit so that we can tell a body
+        // of exactly `limit` bytes apart from a larger one.
+        let max_len = buf.len().min(self.remaining + 1);
+        let len = self.inner.read(&mut buf[..max_len])?;
+        if len > self.remaining {
+            self.limit_exceeded = true;
+            return Err(io::Error::other("Decompressed request body too large"));
+        }
+        self.remaining -= len;
+        Ok(len)
+    }
+}
@@ -0,0 +1,155 @@
+use std::io::{BufWriter, Write};
+
+use actix_web::{
+    http::header::{self, AcceptEncoding, ContentEncoding, Encoding},
+    mime, HttpMessage, HttpRequest, HttpResponse,
+};
+use flate2::{write::GzEncoder, Compression};
+use serde::Serialize;
+
+use crate::channel_writer::{writer_with_stream, BlockingChannelWriter};
+use crate::configuration::CompressionSettings;
+use crate::double_buffered_pipe::RemoteBufWriter;
+use crate::error::ApiError;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The window size for brotli compression, as log2 of the size in bytes.
+const BROTLI_LG_WINDOW_SIZE: u32 = 22;
+
+/// The response encodings we support, in the order in which we prefer them if
+/// the client accepts several encodings with the same quality.
+const SUPPORTED_ENCODINGS: [Encoding; 4] = [
+    Encoding::zstd(),
+    Encoding::brotli(),
+    Encoding::gzip(),
+    Encoding::identity(),
+];
+
+/// Picks the response encoding based on the request's `Accept-Encoding`
+/// header. Requests without this header get an uncompressed response.
+pub fn negotiate_encoding(req: &HttpRequest) -> Result<ContentEncoding, ApiError> {
+    let Some(accept_encoding) = req.get_header::<AcceptEncoding>() else {
+        return Ok(ContentEncoding::Identity);
+    };
+    match accept_encoding.negotiate(SUPPORTED_ENCODINGS.iter()) {
+        Some(Encoding::Known(encoding)) => Ok(encoding),
+        _ => Err(ApiError::NotAcceptable(
+            "None of zstd, br, gzip or identity are acceptable".into(),
+        )),
+    }
+}
+
+/// Creates a response whose body is the JSON serialization of `value`,
+/// compressed with `encoding`.
+///
+/// The serialization runs on a blocking task and is streamed to the client
+/// in chunks, so the serialized JSON never needs to be held in memory in its
+/// entirety.
+pub fn streaming_json_response<T>(
+    value: T,
+    encoding: ContentEncoding,
+    compression: CompressionSettings,
+) -> HttpResponse
+where
+    T: Serialize + Send + 'static,
+{
+    streaming_response(
+        mime::APPLICATION_JSON,
+        encoding,
+        compression,
+        move |writer| {
+            serde_json::to_writer(&mut *writer, &value)?;
+            Ok(value)
+        },
+    )
+}
+
+/// Creates a response whose body is written by `write` on a blocking task,
+/// compressed with `encoding`, and streamed to the client in chunks.
+///
+/// Whatever `write` returns is dropped after the response has ended, so that
+/// expensive deallocations don't delay the end of the response.
+pub fn streaming_response<F, R>(
+    content_type: mime::Mime,
+    encoding: ContentEncoding,
+    compression: CompressionSettings,
+    write: F,
+) -> HttpResponse
+where
+    F: FnOnce(&mut dyn Write) -> Result<R, ApiError> + Send + 'static,
+{
+    let (writer, stream) = writer_with_stream(vec![
+        Vec::with_capacity(CHUNK_SIZE),
+        Vec::with_capacity(CHUNK_SIZE),
+    ]);
+    tokio::task::spawn_blocking(move || {
+        match write_body(writer, encoding, compression, write) {
+            Ok(leftovers) => drop(leftovers), // deallocations after response end
+            Err(e) if e.is_client_disconnect() => {
+                tracing::info!("Client disconnected before the response was complete");
+            }
+            Err(e) => {
+                // The response headers have already been sent, so all we can
+                // do is log the error. The client will see a truncated
+                // response.
+                tracing::error!(code = e.code(), error = %e, "Failed to write response");
+            }
+        }
+    });
+
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(content_type)
+        .append_header((header::VARY, "Accept-Encoding"));
+    if encoding != ContentEncoding::Identity {
+        response.append_header((header::CONTENT_ENCODING, encoding));
+    }
+    response.streaming(stream)
+}
+
+/// Runs `write` with a writer that compresses with `encoding` into `writer`.
+/// Dropping the writer at the end of this function ends the response.
+fn write_body<F, R>(
+    writer: BlockingChannelWriter,
+    encoding: ContentEncoding,
+    compression: CompressionSettings,
+    write: F,
+) -> Result<R, ApiError>
+where
+    F: FnOnce(&mut dyn Write) -> Result<R, ApiError>,
+{
+    let writer = BufWriter::with_capacity(CHUNK_SIZE, writer);
+    let mut writer = RemoteBufWriter::with_capacity(
+        CHUNK_SIZE,
+        compressing_writer(writer, encoding, compression)?,
+    );
+    let leftovers = write(&mut writer)?;
+    writer.flush()?;
+    Ok(leftovers)
+}
+
+/// Wraps `writer` in an encoder for `encoding`. All encoders finish their
+/// stream when they're dropped.
+fn compressing_writer<W: Write + Send + 'static>(
+    writer: W,
+    encoding: ContentEncoding,
+    compression: CompressionSettings,
+) -> std::io::Result<Box<dyn Write + Send>> {
+    Ok(match encoding {
+        ContentEncoding::Gzip => Box::new(GzEncoder::new(
+            writer,
+            Compression::new(compression.gzip_level),
+        )),
+        ContentEncoding::Zstd => Box::new(
+            zstd::stream::write::Encoder::new(writer, compression.zstd_level)?.auto_finish(),
+        ),
+        ContentEncoding::Brotli => Box::new(brotli::CompressorWriter::new(
+            writer,
+            CHUNK_SIZE,
+            compression.brotli_level,
+            BROTLI_LG_WINDOW_SIZE,
+        )),
+        _ => Box::new(writer),
+    })
+}
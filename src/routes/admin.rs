@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::AdminTokens;
+use crate::cache_stats::CacheStats;
+use crate::downloads::{DownloadStatus, DownloadTracker, FinishedDownloadStatus};
+use crate::error::ApiError;
+use crate::negative_cache::NegativeCache;
+use crate::pins::{Pin, PinStatus};
+use crate::prefetch::Prefetcher;
+use crate::quota::QuotaNotifiers;
+use crate::request_body::{parse_json_body, request_encoding, RequestBodyLimit};
+use crate::symbolication::RequestModule;
+
+#[derive(Debug, Serialize)]
+pub struct RemovedResponse {
+    removed: usize,
+}
+
+/// Respond to `DELETE /admin/negative-cache/{debug_name}/{debug_id}` by
+/// forgetting that the symbol servers didn't have this library, e.g. after
+/// its symbols have been uploaded.
+#[tracing::instrument(name = "Clear negative cache entry", skip(req, tokens, negative_cache))]
+pub async fn clear_negative_cache_entry(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    tokens: web::Data<AdminTokens>,
+    negative_cache: web::Data<Option<Arc<NegativeCache>>>,
+) -> Result<HttpResponse, ApiError> {
+    tokens.0.check(&req)?;
+    let negative_cache = enabled_negative_cache(&negative_cache)?;
+    let (debug_name, debug_id) = path.into_inner();
+    let removed = web::block(move || negative_cache.remove(&debug_name, &debug_id))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(RemovedResponse { removed }))
+}
+
+/// Respond to `DELETE /admin/negative-cache` by removing all entries.
+#[tracing::instrument(name = "Clear negative cache", skip(req, tokens, negative_cache))]
+pub async fn clear_negative_cache(
+    req: HttpRequest,
+    tokens: web::Data<AdminTokens>,
+    negative_cache: web::Data<Option<Arc<NegativeCache>>>,
+) -> Result<HttpResponse, ApiError> {
+    tokens.0.check(&req)?;
+    let negative_cache = enabled_negative_cache(&negative_cache)?;
+    let removed = web::block(move || negative_cache.clear())
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(RemovedResponse { removed }))
+}
+
+fn enabled_negative_cache(
+    negative_cache: &web::Data<Option<Arc<NegativeCache>>>,
+) -> Result<Arc<NegativeCache>, ApiError> {
+    negative_cache
+        .get_ref()
+        .clone()
+        .ok_or_else(|| ApiError::NotFound("The negative cache is not enabled".into()))
+}
+
+/// The request body of `/admin/prefetch`.
+#[derive(Debug, Deserialize)]
+pub struct PrefetchRequest {
+    /// The libraries, in the same format as the memory map of a
+    /// `/symbolicate/v5` job: `[debugName, breakpadId]` or
+    /// `[debugName, breakpadId, codeId]`.
+    libraries: Vec<RequestModule>,
+}
+
+/// Respond to `POST /admin/prefetch` by loading the symbols for the
+/// requested libraries in the background. The response is the status of the
+/// new job, which can be polled at `/admin/prefetch/{id}`.
+#[tracing::instrument(name = "Prefetch", skip_all)]
+pub async fn prefetch(
+    req: HttpRequest,
+    payload: web::Payload,
+    body_limit: web::Data<RequestBodyLimit>,
+    tokens: web::Data<AdminTokens>,
+    prefetcher: web::Data<Prefetcher>,
+) -> Result<HttpResponse, ApiError> {
+    tokens.0.check(&req)?;
+    let encoding = request_encoding(&req)?;
+    let request: PrefetchRequest = parse_json_body(payload, encoding, body_limit.0).await?;
+    let mut libraries = request.libraries;
+    let mut seen = HashSet::new();
+    libraries.retain(|library| seen.insert(library.clone()));
+    let id = prefetcher.start(libraries);
+    let status = prefetcher
+        .status(id)
+        .ok_or_else(|| ApiError::Internal(format!("Prefetch job {id} disappeared")))?;
+    Ok(HttpResponse::Accepted()
+        .insert_header(("Location", format!("/admin/prefetch/{id}")))
+        .json(status))
+}
+
+/// Respond to `GET /admin/prefetch/{id}` with the status of a prefetch job.
+#[tracing::instrument(name = "Prefetch status", skip(req, tokens, prefetcher))]
+pub async fn prefetch_status(
+    req: HttpRequest,
+    path: web::Path<u64>,
+    tokens: web::Data<AdminTokens>,
+    prefetcher: web::Data<Prefetcher>,
+) -> Result<HttpResponse, ApiError> {
+    tokens.0.check(&req)?;
+    let id = path.into_inner();
+    let status = prefetcher
+        .status(id)
+        .ok_or_else(|| ApiError::NotFound(format!("No prefetch job with ID {id}")))?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadsResponse {
+    in_flight: Vec<DownloadStatus>,
+    recent: Vec<FinishedDownloadStatus>,
+}
+
+/// Respond to `GET /admin/downloads` with the symbol file downloads which are
+/// running right now, and the ones which finished recently.
+#[tracing::instrument(name = "Downloads", skip_all)]
+pub async fn list_downloads(
+    req: HttpRequest,
+    tokens: web::Data<AdminTokens>,
+    downloads: web::Data<Arc<DownloadTracker>>,
+) -> Result<HttpResponse, ApiError> {
+    tokens.0.check(&req)?;
+    Ok(HttpResponse::Ok().json(DownloadsResponse {
+        in_flight: downloads.in_flight(),
+        recent: downloads.recent(),
+    }))
+}
+
+/// Respond to `GET /admin/cache` with statistics about the quota managed
+/// cache.
+#[tracing::instrument(name = "Cache stats", skip_all)]
+pub async fn cache_stats(
+    req: HttpRequest,
+    tokens: web::Data<AdminTokens>,
+    cache_stats: web::Data<Option<Arc<CacheStats>>>,
+) -> Result<HttpResponse, ApiError> {
+    tokens.0.check(&req)?;
+    let Some(cache_stats) = cache_stats.get_ref().clone() else {
+        return Err(ApiError::NotFound("The cache is not quota managed".into()));
+    };
+    let stats = web::block(move || cache_stats.stats())
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map_err(ApiError::Internal)?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+#[derive(Debug, Serialize)]
+pub struct PinsResponse {
+    pins: Vec<PinStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PinResponse {
+    pin: String,
+    /// The number of cached files of the pinned library, or of the files
+    /// which may be evicted again after unpinning.
+    files: usize,
+}
+
+/// Respond to `GET /admin/pins` with the pinned libraries.
+#[tracing::instrument(name = "Pins", skip_all)]
+pub async fn list_pins(
+    req: HttpRequest,
+    tokens: web::Data<AdminTokens>,
+    quota_notifiers: web::Data<Option<QuotaNotifiers>>,
+) -> Result<HttpResponse, ApiError> {
+    tokens.0.check(&req)?;
+    let quota_notifiers = enabled_quota(&quota_notifiers)?;
+    Ok(HttpResponse::Ok().json(PinsResponse {
+        pins: quota_notifiers.pins().list(),
+    }))
+}
+
+/// Respond to `PUT /admin/pins/{debug_name}` and
+/// `PUT /admin/pins/{debug_name}/{debug_id}` by pinning the library, so that
+/// its cached files are evicted last. The debug name can be a pattern like
+/// `libxul*.so`. The pin is stored in `quota.pins_db_path`.
+#[tracing::instrument(name = "Pin", skip(req, tokens, quota_notifiers))]
+pub async fn pin(
+    req: HttpRequest,
+    path: web::Path<String>,
+    tokens: web::Data<AdminTokens>,
+    quota_notifiers: web::Data<Option<QuotaNotifiers>>,
+) -> Result<HttpResponse, ApiError> {
+    tokens.0.check(&req)?;
+    let quota_notifiers = enabled_quota(&quota_notifiers)?;
+    let pin = Pin::parse(&path.into_inner()).map_err(ApiError::InvalidPath)?;
+    if !quota_notifiers.pins().can_add() {
+        return Err(ApiError::NotFound(
+            "Pinning at runtime is not enabled, set quota.pins_db_path".into(),
+        ));
+    }
+    let response = web::block(move || -> Result<PinResponse, ApiError> {
+        let pins = quota_notifiers.pins();
+        pins.add(pin.clone())
+            .map_err(|e| ApiError::Internal(format!("Could not store the pin: {e}")))?;
+        pins.find_files();
+        Ok(PinResponse {
+            pin: pin.to_string(),
+            files: pins.file_count(&pin),
+        })
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Respond to `DELETE /admin/pins/{debug_name}` and
+/// `DELETE /admin/pins/{debug_name}/{debug_id}` by removing a pin which was
+/// added through the admin API. The library's files are evicted as usual
+/// again, unless another pin covers them.
+#[tracing::instrument(name = "Unpin", skip(req, tokens, quota_notifiers))]
+pub async fn unpin(
+    req: HttpRequest,
+    path: web::Path<String>,
+    tokens: web::Data<AdminTokens>,
+    quota_notifiers: web::Data<Option<QuotaNotifiers>>,
+) -> Result<HttpResponse, ApiError> {
+    tokens.0.check(&req)?;
+    let quota_notifiers = enabled_quota(&quota_notifiers)?;
+    let pin = Pin::parse(&path.into_inner()).map_err(ApiError::InvalidPath)?;
+    if quota_notifiers.pins().is_configured(&pin) {
+        return Err(ApiError::Conflict(format!(
+            "{pin} is pinned in the configuration"
+        )));
+    }
+    let response = web::block(move || -> Result<PinResponse, ApiError> {
+        let pins = quota_notifiers.pins();
+        if !pins
+            .remove(&pin)
+            .map_err(|e| ApiError::Internal(format!("Could not remove the pin: {e}")))?
+        {
+            return Err(ApiError::NotFound(format!("{pin} is not pinned")));
+        }
+        pins.find_files();
+        Ok(PinResponse {
+            pin: pin.to_string(),
+            files: pins.released_file_count(&pin),
+        })
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+fn enabled_quota(
+    quota_notifiers: &web::Data<Option<QuotaNotifiers>>,
+) -> Result<QuotaNotifiers, ApiError> {
+    quota_notifiers
+        .get_ref()
+        .clone()
+        .ok_or_else(|| ApiError::NotFound("The cache is not quota managed".into()))
+}
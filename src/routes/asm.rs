@@ -1,17 +1,35 @@
-use actix_web::{web, Responder};
+use actix_web::{web, HttpRequest, HttpResponse};
 use wholesym::SymbolManager;
 
 use std::sync::Arc;
 
-#[tracing::instrument(name = "Asm v1", skip(contents, symbol_manager))]
+use crate::configuration::CompressionSettings;
+use crate::error::ApiError;
+use crate::request_body::{read_body, request_encoding, RequestBodyLimit};
+use crate::response_body::{negotiate_encoding, streaming_json_response};
+
+#[tracing::instrument(
+    name = "Asm v1",
+    skip(req, payload, symbol_manager, body_limit, compression)
+)]
 pub async fn asm_v1(
-    contents: web::Bytes,
+    req: HttpRequest,
+    payload: web::Payload,
     symbol_manager: web::Data<Arc<SymbolManager>>,
-) -> impl Responder {
-    let request_json = std::str::from_utf8(&contents).unwrap();
+    body_limit: web::Data<RequestBodyLimit>,
+    compression: web::Data<CompressionSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let response_encoding = negotiate_encoding(&req)?;
+    let encoding = request_encoding(&req)?;
+    let contents = read_body(payload, encoding, body_limit.0).await?;
+    let request_json = std::str::from_utf8(&contents)?;
     let response_json = symbol_manager
         .get_ref()
         .query_json_api("/asm/v1", request_json)
         .await;
-    web::Json(response_json)
+    Ok(streaming_json_response(
+        response_json,
+        response_encoding,
+        **compression,
+    ))
 }
@@ -1,4 +1,6 @@
-use actix_web::{http::header::ContentType, HttpResponse, Responder};
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+
+use crate::heartbeat::{CheckStatus, HeartbeatChecker};
 
 const VERSION_JSON: &str = include_str!(concat!(env!("OUT_DIR"), "/version.json"));
 
@@ -12,8 +14,18 @@ pub async fn version() -> HttpResponse {
 /// "Respond to `/__heartbeat__` with a HTTP 200 or 5xx on error. This should check
 /// backing services like a database for connectivity and may respond with the
 /// status of backing services and application components as a JSON payload."
-pub async fn heartbeat() -> impl Responder {
-    HttpResponse::Ok()
+///
+/// We check that our cache directories and databases are writable, that there's
+/// enough free disk space, and optionally that the symbol servers respond.
+/// Only failures of the local checks are errors.
+pub async fn heartbeat(checker: web::Data<HeartbeatChecker>) -> HttpResponse {
+    let response = checker.check().await;
+    if response.status == CheckStatus::Error {
+        tracing::warn!(?response, "Heartbeat check failed");
+        HttpResponse::InternalServerError().json(response)
+    } else {
+        HttpResponse::Ok().json(response)
+    }
 }
 
 /// "Respond to `/__lbheartbeat__` with an HTTP 200. This is for load balancer
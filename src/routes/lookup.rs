@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use actix_web::{
+    http::header::{self, CacheControl, CacheDirective, EntityTag, IfNoneMatch},
+    mime, web, HttpMessage, HttpRequest, HttpResponse,
+};
+use wholesym::SymbolManager;
+
+use crate::error::ApiError;
+use crate::symbolication::{look_up_address, LookupPermits, RequestModule};
+
+/// One year, the conventional maximum for `max-age`.
+const IMMUTABLE_MAX_AGE: u32 = 365 * 24 * 60 * 60;
+
+/// Respond to `/lookup/v1/{debugName}/{debugId}/{hexAddress}` with the
+/// symbol information for a single address, as JSON.
+///
+/// The answer for a given debug ID never changes, so successful responses
+/// can be cached forever by any HTTP cache in front of us.
+#[tracing::instrument(name = "Lookup v1", skip(req, symbol_manager, permits))]
+pub async fn lookup_v1(
+    req: HttpRequest,
+    path: web::Path<(String, String, String)>,
+    symbol_manager: web::Data<Arc<SymbolManager>>,
+    permits: web::Data<LookupPermits>,
+) -> Result<HttpResponse, ApiError> {
+    let (debug_name, debug_id, hex_address) = path.into_inner();
+    let module = RequestModule::try_from((debug_name, debug_id)).map_err(ApiError::InvalidPath)?;
+    let address = parse_hex_address(&hex_address).ok_or_else(|| {
+        ApiError::InvalidPath(format!("Invalid hexadecimal address {hex_address:?}"))
+    })?;
+
+    let response = look_up_address(
+        symbol_manager.get_ref(),
+        permits.get_ref(),
+        &module,
+        address,
+    )
+    .await?;
+    let body = serde_json::to_vec(&response)?;
+
+    let etag = EntityTag::new_strong(format!("{:016x}", fnv1a_hash(&body)));
+
+    let cache_control = CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(IMMUTABLE_MAX_AGE),
+        CacheDirective::Extension("immutable".into(), None),
+    ]);
+
+    let not_modified = match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+    if not_modified {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(cache_control)
+            .insert_header(header::ETag(etag))
+            .finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(mime::APPLICATION_JSON)
+        .insert_header(cache_control)
+        .insert_header(header::ETag(etag))
+        .body(body))
+}
+
+/// The 64-bit FNV-1a hash of `bytes`. Unlike `DefaultHasher`, this doesn't
+/// change between Rust releases, so our ETags stay valid across deployments.
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+pub(crate) fn parse_hex_address(s: &str) -> Option<u64> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u64::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fnv1a_hash() {
+        assert_eq!(fnv1a_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a_hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -0,0 +1,12 @@
+use std::sync::Arc;
+
+use actix_web::{web, HttpResponse};
+
+use crate::metrics::Metrics;
+
+/// Respond to `/metrics` with all metrics in the Prometheus text format.
+pub async fn metrics(metrics: web::Data<Arc<Metrics>>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(metrics.encode())
+}
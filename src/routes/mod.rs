@@ -1,11 +1,25 @@
+pub mod admin;
 pub mod asm;
 pub mod dockerflow;
+pub mod lookup;
+pub mod metrics;
 pub mod root;
 pub mod self_profiles;
+pub mod source;
+pub mod symbol_files;
+pub mod symbol_table;
 pub mod symbolicate;
+pub mod upload;
 
+pub use admin::*;
 pub use asm::*;
 pub use dockerflow::*;
+pub use lookup::*;
+pub use metrics::*;
 pub use root::*;
 pub use self_profiles::{self_profiles_index, self_profiles_latest};
+pub use source::*;
+pub use symbol_files::*;
+pub use symbol_table::*;
 pub use symbolicate::*;
+pub use upload::*;
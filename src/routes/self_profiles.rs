@@ -2,6 +2,8 @@ use std::path::PathBuf;
 
 use actix_web::{web, HttpRequest, HttpResponse};
 
+use crate::error::ApiError;
+
 fn percent_encode(s: &str) -> String {
     let mut out = String::with_capacity(s.len() * 3);
     for byte in s.bytes() {
@@ -18,12 +20,14 @@ fn percent_encode(s: &str) -> String {
 pub async fn self_profiles_index(
     req: HttpRequest,
     dir: web::Data<Option<PathBuf>>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let Some(dir) = dir.as_ref() else {
-        return HttpResponse::NotFound().finish();
+        return Err(ApiError::NotFound("Self profiling is not enabled".into()));
     };
     if !dir.join("latest.json.gz").exists() {
-        return HttpResponse::ServiceUnavailable().body("No profile recorded yet");
+        return Err(ApiError::ServiceUnavailable(
+            "No profile recorded yet".into(),
+        ));
     }
     let conn = req.connection_info();
     let profile_url = format!(
@@ -47,18 +51,20 @@ pub async fn self_profiles_index(
 </html>
 "#
     );
-    HttpResponse::Ok().content_type("text/html").body(html)
+    Ok(HttpResponse::Ok().content_type("text/html").body(html))
 }
 
-pub async fn self_profiles_latest(dir: web::Data<Option<PathBuf>>) -> HttpResponse {
+pub async fn self_profiles_latest(
+    dir: web::Data<Option<PathBuf>>,
+) -> Result<HttpResponse, ApiError> {
     let Some(dir) = dir.as_ref() else {
-        return HttpResponse::NotFound().finish();
+        return Err(ApiError::NotFound("Self profiling is not enabled".into()));
     };
     match tokio::fs::read(dir.join("latest.json.gz")).await {
-        Ok(data) => HttpResponse::Ok()
+        Ok(data) => Ok(HttpResponse::Ok()
             .content_type("application/json")
             .insert_header(("Content-Encoding", "gzip"))
-            .body(data),
-        Err(_) => HttpResponse::NotFound().finish(),
+            .body(data)),
+        Err(_) => Err(ApiError::NotFound("No profile recorded yet".into())),
     }
 }
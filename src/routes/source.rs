@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use wholesym::SymbolManager;
+
+use super::lookup::parse_hex_address;
+use crate::configuration::CompressionSettings;
+use crate::error::ApiError;
+use crate::request_body::{parse_json_body, request_encoding, RequestBodyLimit};
+use crate::response_body::{negotiate_encoding, streaming_json_response};
+use crate::source_files::{MappedSourcePath, SourceFileFetcher};
+use crate::symbolication::{look_up_address, LookupPermits, RequestModule};
+
+/// The request body of `/source/v1`, in the same format as samply's.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceRequest {
+    debug_name: String,
+    debug_id: String,
+    /// The hexadecimal offset of an address whose debug info references
+    /// `file`.
+    module_offset: String,
+    /// The mapped source path, as it appears in `/symbolicate/v5` results.
+    file: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceResponse {
+    symbols_last_modified: Option<String>,
+    source_last_modified: Option<String>,
+    file: String,
+    source: String,
+}
+
+/// Respond to `/source/v1` with the contents of a source file. Source files
+/// can be large, so the response is compressed like the `/symbolicate/v5`
+/// responses.
+///
+/// Only files which are referenced by the debug info of the requested
+/// library at the requested address are served, so that this endpoint
+/// can't be used to fetch arbitrary files from the configured providers.
+#[tracing::instrument(
+    name = "Source v1",
+    skip(
+        req,
+        payload,
+        symbol_manager,
+        permits,
+        body_limit,
+        compression,
+        source_file_fetcher
+    )
+)]
+pub async fn source_v1(
+    req: HttpRequest,
+    payload: web::Payload,
+    symbol_manager: web::Data<Arc<SymbolManager>>,
+    permits: web::Data<LookupPermits>,
+    body_limit: web::Data<RequestBodyLimit>,
+    compression: web::Data<CompressionSettings>,
+    source_file_fetcher: web::Data<Option<SourceFileFetcher>>,
+) -> Result<HttpResponse, ApiError> {
+    let Some(source_file_fetcher) = source_file_fetcher.as_ref() else {
+        return Err(ApiError::NotFound(
+            "Source file fetching is not enabled".into(),
+        ));
+    };
+    let response_encoding = negotiate_encoding(&req)?;
+    let encoding = request_encoding(&req)?;
+    let request: SourceRequest = parse_json_body(payload, encoding, body_limit.0).await?;
+    let mapped_path =
+        MappedSourcePath::parse(&request.file).map_err(ApiError::InvalidSourcePath)?;
+    let module = RequestModule::try_from((request.debug_name, request.debug_id))
+        .map_err(ApiError::InvalidSourcePath)?;
+    let module_offset = parse_hex_address(&request.module_offset).ok_or_else(|| {
+        ApiError::InvalidSourcePath(format!(
+            "Invalid hexadecimal module offset {:?}",
+            request.module_offset
+        ))
+    })?;
+
+    let lookup = look_up_address(
+        symbol_manager.get_ref(),
+        permits.get_ref(),
+        &module,
+        module_offset,
+    )
+    .await?;
+    let is_referenced = lookup.file.as_deref() == Some(&request.file)
+        || lookup
+            .inlines
+            .iter()
+            .any(|inline| inline.file.as_deref() == Some(&request.file));
+    if !is_referenced {
+        return Err(ApiError::NotFound(format!(
+            "{} is not referenced by the debug info at 0x{module_offset:x}",
+            request.file
+        )));
+    }
+
+    let source = source_file_fetcher.get_source(&mapped_path).await?;
+    Ok(streaming_json_response(
+        SourceResponse {
+            symbols_last_modified: None,
+            source_last_modified: None,
+            file: request.file,
+            source,
+        },
+        response_encoding,
+        **compression,
+    ))
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use actix_web::{http::Method, mime, web, HttpRequest, HttpResponse};
+use tokio_util::io::ReaderStream;
+use wholesym::SymbolManager;
+
+use crate::error::ApiError;
+use crate::symbol_files::SymbolFileCache;
+use crate::symbolication::{load_symbol_map, LookupPermits, RequestModule};
+
+/// Respond to `GET` and `HEAD` `/{debugName}/{debugId}/{fileName}` with a
+/// symbol file from our caches, like a Tecken-compatible symbol server.
+///
+/// If a `GET` request asks for a file which isn't cached yet, we first try to
+/// load the symbols for this library from the configured servers, which
+/// downloads the file into the cache if one of them has it. Only a few of
+/// these loads run at the same time. `HEAD` requests only check the cache.
+#[tracing::instrument(name = "Symbol file", skip(req, symbol_manager, permits, cache))]
+pub async fn symbol_file(
+    req: HttpRequest,
+    path: web::Path<(String, String, String)>,
+    symbol_manager: web::Data<Arc<SymbolManager>>,
+    permits: web::Data<LookupPermits>,
+    cache: web::Data<SymbolFileCache>,
+) -> Result<HttpResponse, ApiError> {
+    let (debug_name, debug_id, file_name) = path.into_inner();
+    let mut file_path = cache.find(&debug_name, &debug_id, &file_name).await;
+    if file_path.is_none() && req.method() != Method::HEAD {
+        // Code files (.exe, .dll) are stored under their code ID, which we
+        // can't look up by debug ID, so we only fetch files that are keyed by
+        // a valid debug ID.
+        if let Ok(module) = RequestModule::try_from((debug_name.clone(), debug_id.clone())) {
+            let _permit = cache.fetch_permit().await;
+            // Another request may have fetched the file while we waited.
+            file_path = cache.find(&debug_name, &debug_id, &file_name).await;
+            if file_path.is_none()
+                && load_symbol_map(symbol_manager.get_ref(), permits.get_ref(), &module)
+                    .await
+                    .is_ok()
+            {
+                file_path = cache.find(&debug_name, &debug_id, &file_name).await;
+            }
+        }
+    }
+    let Some(file_path) = file_path else {
+        return Err(ApiError::NotFound(format!(
+            "{debug_name}/{debug_id}/{file_name}"
+        )));
+    };
+
+    let file = tokio::fs::File::open(&file_path).await?;
+    let len = file.metadata().await?.len();
+    cache.report_access(&file_path);
+
+    // actix-web omits the body of responses to HEAD requests, but keeps the
+    // Content-Length header.
+    Ok(HttpResponse::Ok()
+        .content_type(mime::APPLICATION_OCTET_STREAM)
+        .no_chunking(len)
+        .streaming(ReaderStream::new(file)))
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use actix_web::{mime, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use wholesym::SymbolManager;
+
+use crate::configuration::CompressionSettings;
+use crate::error::ApiError;
+use crate::response_body::{negotiate_encoding, streaming_json_response, streaming_response};
+use crate::symbolication::{load_symbol_table, LookupPermits, RequestModule};
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolTableFormat {
+    /// `{ "debugName", "debugId", "symbols": [[address, size, name], ...] }`
+    #[default]
+    Json,
+    /// See [`SymbolTable::write_compact`](crate::symbolication::SymbolTable::write_compact).
+    Compact,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SymbolTableQuery {
+    #[serde(default)]
+    format: SymbolTableFormat,
+}
+
+/// Respond to `/symbol-table/v1/{debugName}/{debugId}` with all symbols of a
+/// library, either as JSON or, with `?format=compact`, in a compact binary
+/// format.
+#[tracing::instrument(
+    name = "Symbol table v1",
+    skip(req, symbol_manager, permits, compression)
+)]
+pub async fn symbol_table_v1(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<SymbolTableQuery>,
+    symbol_manager: web::Data<Arc<SymbolManager>>,
+    permits: web::Data<LookupPermits>,
+    compression: web::Data<CompressionSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let module = RequestModule::try_from(path.into_inner()).map_err(ApiError::InvalidPath)?;
+    let response_encoding = negotiate_encoding(&req)?;
+    let symbol_table =
+        load_symbol_table(symbol_manager.get_ref(), permits.get_ref(), &module).await?;
+    tracing::info!(
+        symbol_count = symbol_table.symbols.len(),
+        "Loaded symbol table"
+    );
+
+    Ok(match query.format {
+        SymbolTableFormat::Json => {
+            streaming_json_response(symbol_table, response_encoding, **compression)
+        }
+        SymbolTableFormat::Compact => streaming_response(
+            mime::APPLICATION_OCTET_STREAM,
+            response_encoding,
+            **compression,
+            move |writer| {
+                symbol_table.write_compact(writer)?;
+                Ok(symbol_table)
+            },
+        ),
+    })
+}
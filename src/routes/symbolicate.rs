@@ -1,46 +1,82 @@
-use actix_web::{
-    http::header::{self, ContentEncoding},
-    mime, web, HttpResponse, Responder,
-};
-use flate2::{write::GzEncoder, Compression};
-use std::{
-    io::{BufWriter, Write},
-    sync::Arc,
-};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use tokio::time::Instant;
 use wholesym::SymbolManager;
 
-use crate::{channel_writer::writer_with_stream, double_buffered_pipe::RemoteBufWriter};
+use crate::configuration::{CompressionSettings, SymbolicationSettings};
+use crate::error::ApiError;
+use crate::request_body::{parse_json_body, request_encoding, RequestBodyLimit};
+use crate::response_body::{negotiate_encoding, streaming_json_response};
+use crate::symbolication::{symbolicate, LookupPermits, SymbolicationRequest};
 
-const CHUNK_SIZE: usize = 64 * 1024;
-const GZIP_COMPRESSION_LEVEL: u32 = 2; // not tweaked
+/// The request header with which clients can ask for a shorter deadline than
+/// the configured one, e.g. `X-Symbolication-Deadline: 10s`.
+const DEADLINE_HEADER: &str = "X-Symbolication-Deadline";
 
-#[tracing::instrument(name = "Symbolicate v5", skip(contents, symbol_manager))]
+#[tracing::instrument(
+    name = "Symbolicate v5",
+    skip(
+        req,
+        payload,
+        symbol_manager,
+        permits,
+        body_limit,
+        compression,
+        settings
+    )
+)]
 pub async fn symbolicate_v5(
-    contents: web::Bytes,
+    req: HttpRequest,
+    payload: web::Payload,
     symbol_manager: web::Data<Arc<SymbolManager>>,
-) -> impl Responder {
-    let request_json = std::str::from_utf8(&contents).unwrap();
-    let response_json = symbol_manager
-        .get_ref()
-        .query_json_api("/symbolicate/v5", request_json)
-        .await;
+    permits: web::Data<LookupPermits>,
+    body_limit: web::Data<RequestBodyLimit>,
+    compression: web::Data<CompressionSettings>,
+    settings: web::Data<SymbolicationSettings>,
+) -> Result<HttpResponse, ApiError> {
+    // The deadline counts from the start of the request, so that time spent
+    // receiving a large request body is included.
+    let deadline = request_deadline(&req, &settings)?.map(|timeout| Instant::now() + timeout);
+    let response_encoding = negotiate_encoding(&req)?;
+    let encoding = request_encoding(&req)?;
+    let request: SymbolicationRequest = parse_json_body(payload, encoding, body_limit.0).await?;
+    let response_json = symbolicate(
+        symbol_manager.get_ref(),
+        permits.get_ref(),
+        &request,
+        deadline,
+    )
+    .await;
+    drop(request);
 
-    let (writer, stream) = writer_with_stream(vec![
-        Vec::with_capacity(CHUNK_SIZE),
-        Vec::with_capacity(CHUNK_SIZE),
-    ]);
-    tokio::task::spawn_blocking(move || {
-        let writer = BufWriter::with_capacity(CHUNK_SIZE, writer);
-        let writer = GzEncoder::new(writer, Compression::new(GZIP_COMPRESSION_LEVEL));
-        let mut writer = RemoteBufWriter::with_capacity(CHUNK_SIZE, writer);
-        serde_json::to_writer(&mut writer, &response_json).unwrap();
-        writer.flush().unwrap();
-        drop(writer); // This ends the response.
-        drop(response_json); // deallocations after response end
-    });
+    Ok(streaming_json_response(
+        response_json,
+        response_encoding,
+        **compression,
+    ))
+}
 
-    HttpResponse::Ok()
-        .content_type(mime::APPLICATION_JSON)
-        .append_header((header::CONTENT_ENCODING, ContentEncoding::Gzip))
-        .streaming(stream)
+/// Returns the shorter one of the configured deadline and the deadline
+/// requested by the client.
+fn request_deadline(
+    req: &HttpRequest,
+    settings: &SymbolicationSettings,
+) -> Result<Option<Duration>, ApiError> {
+    let Some(value) = req.headers().get(DEADLINE_HEADER) else {
+        return Ok(settings.deadline);
+    };
+    let requested = value
+        .to_str()
+        .map_err(|e| e.to_string())
+        .and_then(|s| humantime_serde::re::humantime::parse_duration(s).map_err(|e| e.to_string()))
+        .map_err(|message| ApiError::InvalidHeader {
+            name: DEADLINE_HEADER,
+            message,
+        })?;
+    Ok(Some(match settings.deadline {
+        Some(configured) => requested.min(configured),
+        None => requested,
+    }))
 }
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Serialize;
+
+use crate::error::ApiError;
+use crate::request_body::{process_body, request_encoding};
+use crate::upload::SymbolUploader;
+
+#[derive(Debug, Serialize)]
+pub struct UploadResponse {
+    /// The stored files, relative to the upload directory, e.g.
+    /// `xul.pdb/<breakpadId>/xul.sym`.
+    files: Vec<String>,
+}
+
+/// Respond to `/upload` by storing the Breakpad symbol files in the request
+/// body. The body is either a single `.sym` file, or a zip, tar or tar.gz
+/// archive of a symbol tree in the symbol server layout.
+///
+/// Requires a configured token, in the `Auth-Token` header or as
+/// `Authorization: Bearer <token>`.
+#[tracing::instrument(name = "Upload", skip(req, payload, uploader))]
+pub async fn upload(
+    req: HttpRequest,
+    payload: web::Payload,
+    uploader: web::Data<Option<Arc<SymbolUploader>>>,
+) -> Result<HttpResponse, ApiError> {
+    let Some(uploader) = uploader.get_ref().clone() else {
+        return Err(ApiError::NotFound("Uploads are not enabled".into()));
+    };
+    uploader.tokens().check(&req)?;
+
+    let encoding = request_encoding(&req)?;
+    let limit = uploader.max_size();
+    let files = process_body(payload, encoding, limit, move |reader| {
+        uploader.store_upload(reader)
+    })
+    .await?;
+    tracing::info!(file_count = files.len(), "Stored upload");
+
+    Ok(HttpResponse::Created().json(UploadResponse { files }))
+}
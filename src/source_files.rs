@@ -0,0 +1,394 @@
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use crate::configuration::{SourceMappingKind, SourceProviderSettings, SourceSettings};
+use crate::error::ApiError;
+use crate::quota::QuotaNotifiers;
+
+/// The maximum size of a source file which we fetch and serve.
+const MAX_SOURCE_FILE_SIZE: u64 = 20 * 1000 * 1000; // 20 MB
+
+/// A source path which was mapped by `dump_syms` to a location in a git
+/// repository or in a crate, as written by our release pipeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MappedSourcePath {
+    /// `git:<repo>:<path>:<rev>`, e.g. `git:github.com/mstange/reliost:src/main.rs:<rev>`
+    Git {
+        repo: String,
+        path: String,
+        rev: String,
+    },
+    /// `cargo:<registry>:<crate>-<version>:<path>`
+    Cargo {
+        registry: String,
+        crate_dir: String,
+        path: String,
+    },
+}
+
+impl MappedSourcePath {
+    /// Parses a mapped path and makes sure that none of its parts can be used
+    /// to escape the directories or URLs they're put into.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mapped = if let Some(rest) = s.strip_prefix("git:") {
+            let (repo, rest) = rest
+                .split_once(':')
+                .ok_or_else(|| format!("Missing path in git source path {s:?}"))?;
+            let (path, rev) = rest
+                .rsplit_once(':')
+                .ok_or_else(|| format!("Missing revision in git source path {s:?}"))?;
+            MappedSourcePath::Git {
+                repo: repo.to_owned(),
+                path: path.to_owned(),
+                rev: rev.to_owned(),
+            }
+        } else if let Some(rest) = s.strip_prefix("cargo:") {
+            let mut parts = rest.splitn(3, ':');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(registry), Some(crate_dir), Some(path)) => MappedSourcePath::Cargo {
+                    registry: registry.to_owned(),
+                    crate_dir: crate_dir.to_owned(),
+                    path: path.to_owned(),
+                },
+                _ => return Err(format!("Incomplete cargo source path {s:?}")),
+            }
+        } else {
+            return Err(format!("Unsupported source path {s:?}"));
+        };
+        mapped.validate()?;
+        Ok(mapped)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            MappedSourcePath::Git { repo, path, rev } => {
+                check_relative_path(repo)?;
+                check_relative_path(path)?;
+                if rev.is_empty() || !rev.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(format!("Invalid git revision {rev:?}"));
+                }
+            }
+            MappedSourcePath::Cargo {
+                registry,
+                crate_dir,
+                path,
+            } => {
+                check_path_component(registry)?;
+                check_path_component(crate_dir)?;
+                check_relative_path(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The path of the cached copy of this file, relative to the cache
+    /// directory.
+    fn cache_path(&self) -> PathBuf {
+        match self {
+            MappedSourcePath::Git { repo, path, rev } => ["git", repo, rev, path].iter().collect(),
+            MappedSourcePath::Cargo {
+                registry,
+                crate_dir,
+                path,
+            } => ["cargo", registry, crate_dir, path].iter().collect(),
+        }
+    }
+}
+
+/// Makes sure that `path` is a non-empty relative path which stays inside
+/// the directory it's appended to.
+fn check_relative_path(path: &str) -> Result<(), String> {
+    let is_safe = !path.is_empty()
+        && !path.contains('\\')
+        && Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+    if is_safe {
+        Ok(())
+    } else {
+        Err(format!("Invalid path {path:?} in source path"))
+    }
+}
+
+fn check_path_component(component: &str) -> Result<(), String> {
+    check_relative_path(component)?;
+    if component.contains('/') {
+        return Err(format!(
+            "Invalid path component {component:?} in source path"
+        ));
+    }
+    Ok(())
+}
+
+/// Fetches source files from the configured providers and caches them on
+/// disk.
+pub struct SourceFileFetcher {
+    cache_dir: PathBuf,
+    providers: Vec<SourceProviderSettings>,
+    client: reqwest::Client,
+    quota_manager_notifier: Option<QuotaNotifiers>,
+}
+
+impl SourceFileFetcher {
+    pub fn new(settings: SourceSettings, quota_manager_notifier: Option<QuotaNotifiers>) -> Self {
+        Self {
+            cache_dir: settings.cache_dir,
+            providers: settings.providers,
+            client: reqwest::Client::new(),
+            quota_manager_notifier,
+        }
+    }
+
+    /// Returns the contents of the file at `mapped_path`, from the cache if
+    /// we've fetched it before, or otherwise from the first provider which
+    /// has it.
+    pub async fn get_source(&self, mapped_path: &MappedSourcePath) -> Result<String, ApiError> {
+        let cache_path = self.cache_dir.join(mapped_path.cache_path());
+        match tokio::fs::read(&cache_path).await {
+            Ok(contents) => {
+                if let Some(notifier) = &self.quota_manager_notifier {
+                    notifier.on_file_accessed(&cache_path, SystemTime::now());
+                }
+                return Ok(into_source_string(contents));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        for provider in &self.providers {
+            match self.fetch_from_provider(provider, mapped_path).await {
+                Ok(Some(contents)) => {
+                    if let Err(e) = self.write_to_cache(&cache_path, &contents).await {
+                        tracing::error!(path = ?cache_path, error = %e, "Could not cache source file");
+                    }
+                    return Ok(into_source_string(contents));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!(?provider, error = %e, "Could not fetch source file");
+                }
+            }
+        }
+        Err(ApiError::NotFound(format!(
+            "None of the source providers has {mapped_path:?}"
+        )))
+    }
+
+    async fn fetch_from_provider(
+        &self,
+        provider: &SourceProviderSettings,
+        mapped_path: &MappedSourcePath,
+    ) -> io::Result<Option<Vec<u8>>> {
+        match (provider, mapped_path) {
+            (
+                SourceProviderSettings::Git {
+                    repo: provider_repo,
+                    checkout,
+                },
+                MappedSourcePath::Git { repo, path, rev },
+            ) if provider_repo == repo => fetch_from_git_checkout(checkout, path, rev).await,
+            (
+                SourceProviderSettings::Cargo { registry_src_dir },
+                MappedSourcePath::Cargo {
+                    registry,
+                    crate_dir,
+                    path,
+                },
+            ) => {
+                let file_path: PathBuf = [registry, crate_dir, path].iter().collect();
+                fetch_from_file(&registry_src_dir.join(file_path)).await
+            }
+            (
+                SourceProviderSettings::Http {
+                    mapping,
+                    repo_prefix,
+                    template,
+                },
+                mapped_path,
+            ) => match expand_url_template(*mapping, repo_prefix, template, mapped_path) {
+                Some(url) => self.fetch_from_url(&url).await,
+                None => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    async fn fetch_from_url(&self, url: &str) -> io::Result<Option<Vec<u8>>> {
+        tracing::info!(url, "Fetching source file");
+        let mut response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(io::Error::other(format!(
+                "Unexpected status {} for {url}",
+                response.status()
+            )));
+        }
+        let mut contents = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(io::Error::other)? {
+            contents.extend_from_slice(&chunk);
+            if contents.len() as u64 > MAX_SOURCE_FILE_SIZE {
+                return Err(io::Error::other(format!(
+                    "Source file at {url} is too large"
+                )));
+            }
+        }
+        Ok(Some(contents))
+    }
+
+    /// Writes the file to a temporary file first and then renames it, so that
+    /// concurrent requests never see a partially written file.
+    async fn write_to_cache(&self, cache_path: &Path, contents: &[u8]) -> io::Result<()> {
+        static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let Some(parent) = cache_path.parent() else {
+            return Ok(());
+        };
+        tokio::fs::create_dir_all(parent).await?;
+        let temp_path = cache_path.with_extension(format!(
+            "tmp-{}-{}",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        tokio::fs::write(&temp_path, contents).await?;
+        if let Err(e) = tokio::fs::rename(&temp_path, cache_path).await {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(e);
+        }
+        tracing::info!(path = ?cache_path, size_in_bytes = contents.len(), "Cached source file");
+        if let Some(notifier) = &self.quota_manager_notifier {
+            notifier.on_file_created(cache_path, contents.len() as u64, SystemTime::now());
+            notifier.trigger_eviction_if_needed();
+        }
+        Ok(())
+    }
+}
+
+async fn fetch_from_git_checkout(
+    checkout: &Path,
+    path: &str,
+    rev: &str,
+) -> io::Result<Option<Vec<u8>>> {
+    // `rev` only consists of hex digits, so this can't be mistaken for an
+    // option.
+    let output = tokio::process::Command::new("git")
+        .arg("-C")
+        .arg(checkout)
+        .arg("show")
+        .arg(format!("{rev}:{path}"))
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        tracing::info!(
+            ?checkout,
+            stderr = %String::from_utf8_lossy(&output.stderr),
+            "git show failed"
+        );
+        return Ok(None);
+    }
+    if output.stdout.len() as u64 > MAX_SOURCE_FILE_SIZE {
+        return Err(io::Error::other(format!("{path} at {rev} is too large")));
+    }
+    Ok(Some(output.stdout))
+}
+
+async fn fetch_from_file(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.len() > MAX_SOURCE_FILE_SIZE => {
+            Err(io::Error::other(format!("{path:?} is too large")))
+        }
+        Ok(_) => tokio::fs::read(path).await.map(Some),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn expand_url_template(
+    mapping: SourceMappingKind,
+    repo_prefix: &str,
+    template: &str,
+    mapped_path: &MappedSourcePath,
+) -> Option<String> {
+    match (mapping, mapped_path) {
+        (SourceMappingKind::Git, MappedSourcePath::Git { repo, path, rev }) => {
+            let repo = repo.strip_prefix(repo_prefix)?;
+            Some(
+                template
+                    .replace("{repo}", repo)
+                    .replace("{rev}", rev)
+                    .replace("{path}", path),
+            )
+        }
+        (
+            SourceMappingKind::Cargo,
+            MappedSourcePath::Cargo {
+                registry,
+                crate_dir,
+                path,
+            },
+        ) => Some(
+            template
+                .replace("{registry}", registry)
+                .replace("{crate}", crate_dir)
+                .replace("{path}", path),
+        ),
+        _ => None,
+    }
+}
+
+fn into_source_string(contents: Vec<u8>) -> String {
+    String::from_utf8(contents)
+        .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mapped_paths() {
+        assert_eq!(
+            MappedSourcePath::parse("git:github.com/mstange/reliost:src/main.rs:0123abcd"),
+            Ok(MappedSourcePath::Git {
+                repo: "github.com/mstange/reliost".into(),
+                path: "src/main.rs".into(),
+                rev: "0123abcd".into(),
+            })
+        );
+        assert_eq!(
+            MappedSourcePath::parse(
+                "cargo:index.crates.io-6f17d22bba15001f:serde-1.0.219:src/lib.rs"
+            ),
+            Ok(MappedSourcePath::Cargo {
+                registry: "index.crates.io-6f17d22bba15001f".into(),
+                crate_dir: "serde-1.0.219".into(),
+                path: "src/lib.rs".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_reject_unsafe_mapped_paths() {
+        for path in [
+            "/etc/passwd",
+            "git:github.com/mstange/reliost:../../etc/passwd:0123abcd",
+            "git:github.com/mstange/reliost:/etc/passwd:0123abcd",
+            "git:github.com/mstange/reliost:src/main.rs:--output=x",
+            "git:../reliost:src/main.rs:0123abcd",
+            "cargo:..:serde-1.0.219:src/lib.rs",
+            "cargo:index.crates.io:a/b:src/lib.rs",
+            "cargo:index.crates.io:serde-1.0.219",
+        ] {
+            assert!(MappedSourcePath::parse(path).is_err(), "{path}");
+        }
+    }
+}
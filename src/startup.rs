@@ -3,39 +3,178 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use actix_cors::Cors;
-use actix_web::{dev::Server, web, App, HttpServer};
-use samply_quota_manager::QuotaManager;
+use actix_web::{
+    dev::{Server, ServerHandle},
+    guard, web, App, HttpServer,
+};
 use tracing_actix_web::TracingLogger;
 
+use crate::auth::{AccessTokens, AdminTokens};
+use crate::cache_stats::{CacheStats, EVICTION_POLL_INTERVAL};
 use crate::configuration::Settings;
+use crate::downloads::DownloadTracker;
+use crate::heartbeat::HeartbeatChecker;
+use crate::metrics::{Metrics, RequestMetrics};
+use crate::negative_cache::create_negative_cache;
+use crate::pins::Pins;
+use crate::prefetch::Prefetcher;
+use crate::quota::{QuotaManagers, QuotaNotifiers, QUOTA_CHECK_INTERVAL, QUOTA_CHECK_MIN_DELAY};
+use crate::request_body::{RequestBodyLimit, DEFAULT_MAX_REQUEST_BODY_SIZE};
 use crate::routes::{
-    asm_v1, greet, heartbeat, lbheartbeat, self_profiles_index, self_profiles_latest,
-    symbolicate_v5, version,
+    asm_v1, cache_stats, clear_negative_cache, clear_negative_cache_entry, greet, heartbeat,
+    lbheartbeat, list_downloads, list_pins, lookup_v1, metrics, pin, prefetch, prefetch_status,
+    self_profiles_index, self_profiles_latest, source_v1, symbol_file, symbol_table_v1,
+    symbolicate_v5, unpin, upload, version,
 };
+use crate::source_files::SourceFileFetcher;
+use crate::symbol_files::SymbolFileCache;
 use crate::symbol_manager::create_symbol_manager_and_quota_manager;
+use crate::symbolication::LookupPermits;
+use crate::upload::SymbolUploader;
+use crate::upstream_proxy::UpstreamProxy;
 
+/// Returns the server, and what needs to be shut down after it stops: the
+/// quota managers, and the upstream proxy if there is one.
 #[tracing::instrument(skip_all)]
 pub fn run(
     listener: TcpListener,
     settings: Settings,
-) -> Result<(Server, Option<QuotaManager>), std::io::Error> {
+) -> Result<(Server, QuotaManagers, Option<ServerHandle>), std::io::Error> {
+    if let Some(quota) = &settings.quota {
+        quota
+            .validate()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    }
+    if let Some(debuginfod) = settings
+        .symbols
+        .as_ref()
+        .and_then(|symbols| symbols.debuginfod.as_ref())
+    {
+        debuginfod
+            .validate()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    }
     let self_profiles_dir: web::Data<Option<PathBuf>> =
         web::Data::new(settings.self_profiles.as_ref().map(|s| s.dir.clone()));
-    let (symbol_manager, quota_manager) = create_symbol_manager_and_quota_manager(settings);
-    let app_data = web::Data::new(Arc::new(symbol_manager));
+    let request_body_limit = web::Data::new(RequestBodyLimit(
+        settings
+            .server
+            .max_request_body_size
+            .map_or(DEFAULT_MAX_REQUEST_BODY_SIZE, |size| {
+                usize::try_from(size).unwrap_or(usize::MAX)
+            }),
+    ));
+    let heartbeat_checker = HeartbeatChecker::new(&settings);
+    let pins = Arc::new(Pins::new(&settings));
+    let cache_stats_data =
+        web::Data::new(CacheStats::new(&settings, Arc::clone(&pins)).map(Arc::new));
+    if let Some(cache_stats) = cache_stats_data.get_ref().clone() {
+        tokio::spawn(poll_evictions(cache_stats));
+    }
+    let compression = web::Data::new(settings.compression);
+    let symbolication_settings = web::Data::new(settings.symbolication);
+    let source_settings = settings.source.clone();
+    let symbol_settings = settings.symbols.clone();
+    let upload_settings = settings.upload.clone();
+    let admin_tokens = AccessTokens::read(&settings.admin.tokens).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Could not read the admin tokens: {e}"),
+        )
+    })?;
+    let admin_tokens = web::Data::new(AdminTokens(admin_tokens));
+    let negative_cache = create_negative_cache(symbol_settings.as_ref()).map(Arc::new);
+    let (upstream_proxy, upstream_proxy_server) =
+        UpstreamProxy::start(&settings, negative_cache.clone())?;
+    let upstream_proxy_handle = upstream_proxy_server.map(|upstream_proxy_server| {
+        let handle = upstream_proxy_server.handle();
+        tokio::spawn(upstream_proxy_server);
+        handle
+    });
+    let downloads = Arc::new(DownloadTracker::default());
+    let metrics_registry = Arc::new(Metrics::default());
+    let (symbol_manager, quota_managers) = create_symbol_manager_and_quota_manager(
+        settings,
+        &upstream_proxy,
+        Arc::clone(&downloads),
+        Arc::clone(&metrics_registry),
+        pins,
+    );
+    let heartbeat_checker =
+        web::Data::new(heartbeat_checker.with_quota_health(quota_managers.health()));
+    let quota_notifiers = quota_managers.notifiers();
+    if let Some(quota_notifiers) = &quota_notifiers {
+        tokio::spawn(check_quotas(quota_notifiers.clone()));
+    }
+    let symbol_file_cache = web::Data::new(SymbolFileCache::new(
+        symbol_settings.as_ref(),
+        upload_settings.as_ref(),
+        quota_notifiers.clone(),
+    ));
+    let symbol_uploader = upload_settings
+        .map(|upload_settings| {
+            SymbolUploader::new(upload_settings, quota_notifiers.clone()).map(Arc::new)
+        })
+        .transpose()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let symbol_uploader = web::Data::new(symbol_uploader);
+    let source_file_fetcher =
+        web::Data::new(source_settings.map(|source_settings| {
+            SourceFileFetcher::new(source_settings, quota_notifiers.clone())
+        }));
+    let quota_notifiers_data = web::Data::new(quota_notifiers);
+    let negative_cache = web::Data::new(negative_cache);
+    let symbol_manager = Arc::new(symbol_manager);
+    let lookup_permits = web::Data::new(LookupPermits::default());
+    let prefetcher = web::Data::new(Prefetcher::new(
+        Arc::clone(&symbol_manager),
+        lookup_permits.get_ref().clone(),
+        Arc::clone(&downloads),
+        symbol_file_cache.clone().into_inner(),
+    ));
+    let metrics_data = web::Data::new(Arc::clone(&metrics_registry));
+    let download_tracker = web::Data::new(Arc::clone(&downloads));
+    let app_data = web::Data::new(symbol_manager);
     let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
-            .allowed_methods(vec!["GET", "POST", "OPTION"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTION"])
             .allow_any_header()
             .send_wildcard()
             .max_age(86400);
         App::new()
             .wrap(cors)
+            .wrap(RequestMetrics(Arc::clone(&metrics_registry)))
             .wrap(TracingLogger::default())
             .route("/", web::get().to(greet))
             .route("/symbolicate/v5", web::post().to(symbolicate_v5))
+            .route(
+                "/symbol-table/v1/{debug_name}/{debug_id}",
+                web::get().to(symbol_table_v1),
+            )
             .route("/asm/v1", web::post().to(asm_v1))
+            .route("/source/v1", web::post().to(source_v1))
+            .route("/upload", web::post().to(upload))
+            .route(
+                "/lookup/v1/{debug_name}/{debug_id}/{address}",
+                web::get().to(lookup_v1),
+            )
+            .route(
+                "/admin/negative-cache",
+                web::delete().to(clear_negative_cache),
+            )
+            .route(
+                "/admin/negative-cache/{debug_name}/{debug_id}",
+                web::delete().to(clear_negative_cache_entry),
+            )
+            .route("/admin/cache", web::get().to(cache_stats))
+            .route("/admin/downloads", web::get().to(list_downloads))
+            .route("/admin/pins", web::get().to(list_pins))
+            // The pin is `<debugName>` or `<debugName>/<debugId>`.
+            .route("/admin/pins/{pin:.+}", web::put().to(pin))
+            .route("/admin/pins/{pin:.+}", web::delete().to(unpin))
+            .route("/admin/prefetch", web::post().to(prefetch))
+            .route("/admin/prefetch/{id}", web::get().to(prefetch_status))
             .route("/self-profiles/", web::get().to(self_profiles_index))
             .route(
                 "/self-profiles/latest.json.gz",
@@ -46,11 +185,72 @@ pub fn run(
             .route("/__version__", web::get().to(version))
             .route("/__heartbeat__", web::get().to(heartbeat))
             .route("/__lbheartbeat__", web::get().to(lbheartbeat))
+            .route("/metrics", web::get().to(metrics))
+            // Symbol server layout, for clients which use us as their symbol
+            // server. This needs to come last because it matches any path
+            // with three segments.
+            .route(
+                "/{debug_name}/{debug_id}/{file_name}",
+                web::route()
+                    .guard(guard::Any(guard::Get()).or(guard::Head()))
+                    .to(symbol_file),
+            )
             .app_data(app_data.clone())
+            .app_data(lookup_permits.clone())
             .app_data(self_profiles_dir.clone())
-            .app_data(web::PayloadConfig::new(100 * 1000 * 1000)) // 100 MB
+            .app_data(request_body_limit.clone())
+            .app_data(compression.clone())
+            .app_data(symbolication_settings.clone())
+            .app_data(source_file_fetcher.clone())
+            .app_data(symbol_file_cache.clone())
+            .app_data(symbol_uploader.clone())
+            .app_data(admin_tokens.clone())
+            .app_data(negative_cache.clone())
+            .app_data(prefetcher.clone())
+            .app_data(metrics_data.clone())
+            .app_data(heartbeat_checker.clone())
+            .app_data(download_tracker.clone())
+            .app_data(cache_stats_data.clone())
+            .app_data(quota_notifiers_data.clone())
     })
+    // Abort request handling as soon as the client closes its connection, for
+    // example because a profiler tab was closed. This drops the handler's
+    // future, which cancels any symbol lookups and downloads it was waiting on.
+    .h1_allow_half_closed(false)
     .listen(listener)?
     .run();
-    Ok((server, quota_manager))
+    Ok((server, quota_managers, upstream_proxy_handle))
+}
+
+/// Notices evictions by the quota manager, for `/admin/cache`.
+async fn poll_evictions(cache_stats: Arc<CacheStats>) {
+    let mut interval = tokio::time::interval(EVICTION_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let cache_stats = Arc::clone(&cache_stats);
+        match tokio::task::spawn_blocking(move || cache_stats.detect_evictions()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!(error = e, "Could not read the quota database"),
+            Err(e) => tracing::error!(error = %e, "Eviction polling panicked"),
+        }
+    }
+}
+
+/// Enforces the quotas and the configured free disk space, periodically and
+/// after new files, at most once per [`QUOTA_CHECK_MIN_DELAY`]. The heartbeat
+/// fails if this stops.
+async fn check_quotas(quota_notifiers: QuotaNotifiers) {
+    let mut interval = tokio::time::interval(QUOTA_CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = quota_notifiers.file_created() => {}
+        }
+        let quota_notifiers = quota_notifiers.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || quota_notifiers.check_quotas()).await {
+            tracing::error!(error = %e, "Quota check panicked");
+        }
+        // The files which are created in the meantime are checked together.
+        tokio::time::sleep(QUOTA_CHECK_MIN_DELAY).await;
+    }
 }
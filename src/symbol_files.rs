@@ -0,0 +1,95 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::configuration::{SymbolSettings, UploadSettings};
+use crate::quota::QuotaNotifiers;
+
+/// How many symbol files are fetched for symbol server clients and prefetch
+/// jobs at the same time. Fetching a file loads all of its symbols, which is
+/// expensive for large libraries, and anyone can ask for any file.
+const MAX_CONCURRENT_FETCHES: usize = 4;
+
+/// The symbol files in our breakpad and windows directories and in the upload
+/// directory, which we serve to other symbol server clients.
+///
+/// All directories use the symbol server layout
+/// `<debugName>/<debugId>/<fileName>`, e.g. `xul.pdb/<id>/xul.sym` or
+/// `xul.pdb/<id>/xul.pdb`.
+pub struct SymbolFileCache {
+    dirs: Vec<PathBuf>,
+    quota_manager_notifier: Option<QuotaNotifiers>,
+    fetch_permits: Semaphore,
+}
+
+impl SymbolFileCache {
+    pub fn new(
+        settings: Option<&SymbolSettings>,
+        upload_settings: Option<&UploadSettings>,
+        quota_manager_notifier: Option<QuotaNotifiers>,
+    ) -> Self {
+        let mut dirs = Vec::new();
+        if let Some(symbols) = settings {
+            if let Some(breakpad) = &symbols.breakpad {
+                dirs.extend(breakpad.dirs.iter().cloned());
+                dirs.push(breakpad.cache_dir.clone());
+            }
+            if let Some(windows) = &symbols.windows {
+                dirs.extend(windows.dirs.iter().cloned());
+                dirs.push(windows.cache_dir.clone());
+            }
+        }
+        if let Some(upload) = upload_settings {
+            dirs.push(upload.dir.clone());
+        }
+        Self {
+            dirs,
+            quota_manager_notifier,
+            fetch_permits: Semaphore::new(MAX_CONCURRENT_FETCHES),
+        }
+    }
+
+    /// Waits until we can fetch another file from the symbol servers.
+    pub async fn fetch_permit(&self) -> SemaphorePermit<'_> {
+        // The semaphore is never closed.
+        self.fetch_permits.acquire().await.unwrap()
+    }
+
+    /// Returns the path of the cached file, if we have it. Returns `None` if
+    /// any of the path components could escape the cache directories.
+    pub async fn find(&self, debug_name: &str, debug_id: &str, file_name: &str) -> Option<PathBuf> {
+        if ![debug_name, debug_id, file_name]
+            .into_iter()
+            .all(is_safe_path_component)
+        {
+            return None;
+        }
+        for dir in &self.dirs {
+            let path = dir.join(debug_name).join(debug_id).join(file_name);
+            if tokio::fs::metadata(&path)
+                .await
+                .is_ok_and(|metadata| metadata.is_file())
+            {
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    /// Tells the quota manager that the file at `path` was used, so that it
+    /// is evicted after files which haven't been used recently.
+    pub fn report_access(&self, path: &Path) {
+        tracing::info!(path = path.to_string_lossy().to_string(), "File accessed");
+        if let Some(notifier) = &self.quota_manager_notifier {
+            notifier.on_file_accessed(path, SystemTime::now());
+        }
+    }
+}
+
+fn is_safe_path_component(component: &str) -> bool {
+    !component.is_empty()
+        && component != "."
+        && component != ".."
+        && !component.contains(['/', '\\', '\0'])
+}
@@ -1,26 +1,31 @@
 use std::sync::Arc;
 
-use samply_quota_manager::QuotaManager;
 use wholesym::{SymbolManager, SymbolManagerConfig};
 
-use crate::configuration::{QuotaSettings, Settings};
+use crate::configuration::Settings;
+use crate::downloads::DownloadTracker;
+use crate::metrics::Metrics;
+use crate::pins::Pins;
+use crate::quota::{create_quota_managers, QuotaManagers};
 use crate::symbol_manager_observer::QuotaManagingSymbolManagerObserver;
+use crate::upstream_proxy::UpstreamProxy;
 
 #[tracing::instrument(name = "Create symbol manager", skip_all)]
 pub fn create_symbol_manager_and_quota_manager(
     settings: Settings,
-) -> (SymbolManager, Option<QuotaManager>) {
-    let config = create_symbol_manager_config(&settings);
-    let quota_manager = create_quota_manager(&settings);
+    upstream_proxy: &UpstreamProxy,
+    downloads: Arc<DownloadTracker>,
+    metrics: Arc<Metrics>,
+    pins: Arc<Pins>,
+) -> (SymbolManager, QuotaManagers) {
+    let config = create_symbol_manager_config(&settings, upstream_proxy);
+    let quota_managers = create_quota_managers(&settings, pins);
 
-    let quota_manager_notifiers = match &quota_manager {
-        Some(qm) => {
-            let notifier = qm.notifier();
-
-            // Enforce size and age limit now
-            notifier.trigger_eviction_if_needed();
-
-            vec![notifier]
+    let quota_notifiers = quota_managers.notifiers();
+    match &quota_notifiers {
+        Some(notifiers) => {
+            // Enforce size and age limits now
+            notifiers.trigger_eviction_if_needed();
         }
         None => {
             tracing::warn!(
@@ -29,25 +34,40 @@ pub fn create_symbol_manager_and_quota_manager(
             tracing::warn!(
                 "Without a quota manager, downloaded files will accumulate without bound."
             );
-            vec![]
         }
-    };
+    }
 
     let mut symbol_manager = SymbolManager::with_config(config);
-    let observer = QuotaManagingSymbolManagerObserver::new(quota_manager_notifiers);
+    let observer = QuotaManagingSymbolManagerObserver::new(
+        quota_notifiers,
+        downloads,
+        metrics,
+        upstream_proxy.clone(),
+    );
     symbol_manager.set_observer(Some(Arc::new(observer)));
-    (symbol_manager, quota_manager)
+    (symbol_manager, quota_managers)
 }
 
-fn create_symbol_manager_config(settings: &Settings) -> SymbolManagerConfig {
+fn create_symbol_manager_config(
+    settings: &Settings,
+    upstream_proxy: &UpstreamProxy,
+) -> SymbolManagerConfig {
     let mut config = SymbolManagerConfig::default();
     if let Some(symbols) = settings.symbols.as_ref() {
         if let Some(breakpad) = symbols.breakpad.as_ref() {
+            // wholesym searches all local directories before it checks any
+            // servers, each in the order in which they were added.
+            for dir in &breakpad.dirs {
+                config = config.breakpad_symbol_dir(dir);
+            }
             if breakpad.servers.is_empty() {
                 config = config.breakpad_symbol_dir(&breakpad.cache_dir);
             } else {
-                for server_url in &breakpad.servers {
-                    config = config.breakpad_symbol_server(server_url, breakpad.cache_dir.clone());
+                for server in &breakpad.servers {
+                    config = config.breakpad_symbol_server(
+                        upstream_proxy.url_for(server),
+                        breakpad.cache_dir.clone(),
+                    );
                 }
             }
             if let Some(symindex_dir) = &breakpad.symindex_dir {
@@ -55,34 +75,44 @@ fn create_symbol_manager_config(settings: &Settings) -> SymbolManagerConfig {
             }
         }
         if let Some(windows) = symbols.windows.as_ref() {
-            for server_url in &windows.servers {
-                config = config.windows_symbol_server(server_url, windows.cache_dir.clone());
-            }
+            // The local symbol stores and the servers go into one symbol
+            // path, which wholesym searches in order. Entries without a
+            // server are local symbol stores. _NT_SYMBOL_PATH is ignored, so
+            // that the environment can't change where we look.
+            let local_stores = windows
+                .dirs
+                .iter()
+                .map(|dir| format!("srv*{}", dir.display()));
+            let servers = windows.servers.iter().map(|server| {
+                format!(
+                    "srv*{}*{}",
+                    windows.cache_dir.display(),
+                    upstream_proxy.url_for(server)
+                )
+            });
+            let symbol_path = local_stores.chain(servers).collect::<Vec<_>>().join(";");
+            config = config
+                .respect_nt_symbol_path(false)
+                .default_nt_symbol_path(symbol_path);
         }
     }
-    config
-}
-
-fn create_quota_manager(settings: &Settings) -> Option<QuotaManager> {
-    let QuotaSettings {
-        managed_dir,
-        db_path,
-        size_limit,
-        age_limit,
-    } = settings.quota.as_ref()?.clone();
-
-    if let Err(e) = std::fs::create_dir_all(&managed_dir) {
-        panic!("Could not create quota managed directory {managed_dir:?}: {e}");
-    }
-
-    let quota_manager = match QuotaManager::new(&managed_dir, &db_path) {
-        Ok(quota_manager) => quota_manager,
-        Err(e) => {
-            panic!("Could not create QuotaManager with symbol cache database {db_path:?}: {e}");
+    if let Some(debuginfod) = settings
+        .symbols
+        .as_ref()
+        .and_then(|symbols| symbols.debuginfod.as_ref())
+    {
+        // If debuginfod is installed, wholesym uses the system's client,
+        // which caches files in DEBUGINFOD_CACHE_PATH rather than in the
+        // directory we give wholesym. `run` checks that the two agree.
+        config = config
+            .use_debuginfod(true)
+            .debuginfod_cache_dir_if_not_installed(&debuginfod.cache_dir);
+        for server_url in &debuginfod.servers {
+            config = config.extra_debuginfod_server(server_url);
         }
-    };
-
-    quota_manager.set_max_total_size(size_limit);
-    quota_manager.set_max_age(age_limit.map(|d| d.as_secs()));
-    Some(quota_manager)
+    }
+    if let Some(upload) = settings.upload.as_ref() {
+        config = config.breakpad_symbol_dir(&upload.dir);
+    }
+    config
 }
@@ -1,45 +1,78 @@
-use std::collections::HashMap;
 use std::path::Path;
-use std::sync::Mutex;
+use std::sync::Arc;
 use std::time::SystemTime;
 
-use samply_quota_manager::QuotaManagerNotifier;
 use wholesym::{DownloadError, SymbolManagerObserver};
 
+use crate::downloads::{DownloadOutcome, DownloadTracker};
+use crate::metrics::Metrics;
+use crate::quota::QuotaNotifiers;
+use crate::upstream_proxy::UpstreamProxy;
+
 pub struct QuotaManagingSymbolManagerObserver {
-    quota_manager_notifiers: Vec<QuotaManagerNotifier>,
-    urls: Mutex<HashMap<u64, String>>,
+    quota_notifiers: Option<QuotaNotifiers>,
+    downloads: Arc<DownloadTracker>,
+    metrics: Arc<Metrics>,
+    upstream_proxy: UpstreamProxy,
 }
 
 impl QuotaManagingSymbolManagerObserver {
-    pub fn new(quota_manager_notifiers: Vec<QuotaManagerNotifier>) -> Self {
+    pub fn new(
+        quota_notifiers: Option<QuotaNotifiers>,
+        downloads: Arc<DownloadTracker>,
+        metrics: Arc<Metrics>,
+        upstream_proxy: UpstreamProxy,
+    ) -> Self {
         Self {
-            quota_manager_notifiers,
-            urls: Mutex::new(HashMap::new()),
+            quota_notifiers,
+            downloads,
+            metrics,
+            upstream_proxy,
         }
     }
+
+    /// The host of the server that `url` is downloaded from, even if the
+    /// download goes through the upstream proxy.
+    fn upstream_host(&self, url: &str) -> String {
+        let url = self.upstream_proxy.original_url(url);
+        reqwest::Url::parse(&url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+            .unwrap_or_else(|| "unknown".to_owned())
+    }
+}
+
+/// The name of the `DownloadError` variant, e.g. `StatusError` or `Timeout`,
+/// for the metrics label.
+fn download_error_kind(error: &DownloadError) -> &'static str {
+    match error {
+        DownloadError::ClientCreationFailed(_) => "ClientCreationFailed",
+        DownloadError::OpenFailed(_) => "OpenFailed",
+        DownloadError::Timeout => "Timeout",
+        DownloadError::StatusError(_) => "StatusError",
+        DownloadError::CouldNotCreateDestinationDirectory => "CouldNotCreateDestinationDirectory",
+        DownloadError::UnexpectedContentEncoding(_) => "UnexpectedContentEncoding",
+        DownloadError::ErrorDuringDownloading(_) => "ErrorDuringDownloading",
+        DownloadError::ErrorWhileWritingDownloadedFile(_) => "ErrorWhileWritingDownloadedFile",
+        DownloadError::Redirect(_) => "Redirect",
+        DownloadError::Other(_) => "Other",
+    }
 }
 
 impl SymbolManagerObserver for QuotaManagingSymbolManagerObserver {
     fn on_new_download_before_connect(&self, download_id: u64, url: &str) {
         tracing::info!(url, "Connecting to URL");
-        self.urls
-            .lock()
-            .unwrap()
-            .insert(download_id, url.to_owned());
+        self.downloads.on_connect(download_id, url);
     }
 
     fn on_download_started(&self, download_id: u64) {
-        let url = self.urls.lock().unwrap().get(&download_id).unwrap().clone();
+        let url = self.downloads.url(download_id).unwrap();
         tracing::info!(url, "Downloading from URL");
     }
 
-    fn on_download_progress(
-        &self,
-        _download_id: u64,
-        _bytes_so_far: u64,
-        _total_bytes: Option<u64>,
-    ) {
+    fn on_download_progress(&self, download_id: u64, bytes_so_far: u64, total_bytes: Option<u64>) {
+        self.downloads
+            .on_progress(download_id, bytes_so_far, total_bytes);
     }
 
     fn on_download_completed(
@@ -49,7 +82,20 @@ impl SymbolManagerObserver for QuotaManagingSymbolManagerObserver {
         time_until_headers: std::time::Duration,
         time_until_completed: std::time::Duration,
     ) {
-        let url = self.urls.lock().unwrap().remove(&download_id).unwrap();
+        let url = self
+            .downloads
+            .on_finished(
+                download_id,
+                DownloadOutcome::Completed,
+                Some(uncompressed_size_in_bytes),
+            )
+            .unwrap();
+        self.metrics.on_download_completed(
+            &self.upstream_host(&url),
+            uncompressed_size_in_bytes,
+            time_until_headers,
+            time_until_completed,
+        );
         tracing::info!(
             url,
             uncompressed_size_in_bytes,
@@ -60,7 +106,15 @@ impl SymbolManagerObserver for QuotaManagingSymbolManagerObserver {
     }
 
     fn on_download_failed(&self, download_id: u64, reason: DownloadError) {
-        let url = self.urls.lock().unwrap().remove(&download_id).unwrap();
+        let outcome = DownloadOutcome::Failed {
+            error: reason.to_string(),
+        };
+        let url = self
+            .downloads
+            .on_finished(download_id, outcome, None)
+            .unwrap();
+        self.metrics
+            .on_download_failed(&self.upstream_host(&url), download_error_kind(&reason));
         tracing::info!(
             url,
             reason = reason.to_string(),
@@ -69,7 +123,12 @@ impl SymbolManagerObserver for QuotaManagingSymbolManagerObserver {
     }
 
     fn on_download_canceled(&self, download_id: u64) {
-        let url = self.urls.lock().unwrap().remove(&download_id).unwrap();
+        // Downloads are canceled when the last request waiting for them goes
+        // away, which can happen at any point, so don't assume that we've
+        // seen this download before.
+        let url = self
+            .downloads
+            .on_finished(download_id, DownloadOutcome::Canceled, None);
         tracing::info!(url, "Canceled download from URL");
     }
 
@@ -79,20 +138,22 @@ impl SymbolManagerObserver for QuotaManagingSymbolManagerObserver {
             size_in_bytes,
             "Created new file"
         );
-        for notifier in &self.quota_manager_notifiers {
+        if let Some(notifier) = &self.quota_notifiers {
             notifier.on_file_created(path, size_in_bytes, SystemTime::now());
             notifier.trigger_eviction_if_needed();
         }
     }
 
     fn on_file_accessed(&self, path: &Path) {
+        self.metrics.on_file_hit();
         tracing::info!(path = path.to_string_lossy().to_string(), "File accessed");
-        for notifier in &self.quota_manager_notifiers {
+        if let Some(notifier) = &self.quota_notifiers {
             notifier.on_file_accessed(path, SystemTime::now());
         }
     }
 
     fn on_file_missed(&self, path: &Path) {
+        self.metrics.on_file_missed();
         tracing::info!(
             path = path.to_string_lossy().to_string(),
             "File access missed"
@@ -0,0 +1,406 @@
+pub mod request;
+pub mod response;
+pub mod symbol_table;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use wholesym::{AddressInfo, LibraryInfo, LookupAddress, SymbolManager, SymbolMap};
+
+pub use request::*;
+pub use response::*;
+pub use symbol_table::*;
+
+/// How many libraries are loaded at the same time, across all symbolication
+/// requests.
+const MAX_CONCURRENT_LOOKUPS: usize = 16;
+
+/// Limits how many libraries are loaded at the same time, across all routes,
+/// so that requests with many libraries, or lookups which outlive their
+/// request's deadline, can't start an unbounded number of downloads.
+#[derive(Clone)]
+pub struct LookupPermits(Arc<Semaphore>);
+
+impl Default for LookupPermits {
+    fn default() -> Self {
+        Self(Arc::new(Semaphore::new(MAX_CONCURRENT_LOOKUPS)))
+    }
+}
+
+impl LookupPermits {
+    /// Waits for a permit, or returns `None` if `deadline` is reached first.
+    async fn acquire(&self, deadline: Option<Instant>) -> Option<OwnedSemaphorePermit> {
+        let permit = Arc::clone(&self.0).acquire_owned();
+        let permit = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, permit).await.ok()?,
+            None => permit.await,
+        };
+        // The semaphore is never closed.
+        permit.ok()
+    }
+}
+
+/// The lookup results for a single library, shared by all jobs which
+/// reference it.
+enum ModuleLookupResult {
+    Found(HashMap<u64, AddressInfo>),
+    Error(ModuleError),
+}
+
+/// Symbolicates all jobs of `request`.
+///
+/// Each library is only loaded once, even if it is referenced by multiple
+/// jobs. Libraries are loaded concurrently, each on its own task, as far as
+/// `permits` allow.
+///
+/// If `deadline` is reached before all libraries have been loaded, the
+/// response contains the results for the libraries which have been loaded
+/// so far, and a timeout error for each of the others. Lookups which have
+/// started keep running in the background so that the downloaded files are
+/// cached for the next request, and the ones still waiting for a permit are
+/// dropped.
+#[tracing::instrument(name = "Symbolicate", skip_all)]
+pub async fn symbolicate(
+    symbol_manager: &Arc<SymbolManager>,
+    permits: &LookupPermits,
+    request: &SymbolicationRequest,
+    deadline: Option<Instant>,
+) -> SymbolicationResponse {
+    let addresses_per_module = collect_addresses_per_module(request);
+    tracing::info!(
+        job_count = request.jobs.len(),
+        module_count = addresses_per_module.len(),
+        address_count = addresses_per_module
+            .values()
+            .map(BTreeSet::len)
+            .sum::<usize>(),
+        "Symbolicating"
+    );
+
+    let modules: Vec<RequestModule> = addresses_per_module
+        .keys()
+        .map(|&module| module.clone())
+        .collect();
+    let (result_tx, mut result_rx) = mpsc::unbounded_channel();
+    let mut lookup_tasks = LookupTasks::default();
+    for (module, addresses) in addresses_per_module {
+        let module = module.clone();
+        let symbol_manager = Arc::clone(symbol_manager);
+        let permits = permits.clone();
+        let result_tx = result_tx.clone();
+        lookup_tasks.0.push(tokio::spawn(async move {
+            let result =
+                look_up_module(&symbol_manager, &permits, &module, &addresses, deadline).await;
+            let _ = result_tx.send((module, result));
+        }));
+    }
+    drop(result_tx);
+
+    let mut lookup_results = HashMap::new();
+    let receive_all = async {
+        while let Some((module, result)) = result_rx.recv().await {
+            lookup_results.insert(module, result);
+        }
+    };
+    match deadline {
+        Some(deadline) => {
+            let _ = tokio::time::timeout_at(deadline, receive_all).await;
+        }
+        None => receive_all.await,
+    }
+    lookup_tasks.detach();
+
+    // Libraries without a result were still loading, or still waiting for a
+    // permit, when the deadline was reached.
+    let timed_out_count = modules.len() - lookup_results.len();
+    if timed_out_count > 0 {
+        tracing::info!(
+            timed_out_count,
+            "Symbolication deadline exceeded, returning partial results"
+        );
+        for module in modules {
+            lookup_results
+                .entry(module)
+                .or_insert_with(|| ModuleLookupResult::Error(timeout_error()));
+        }
+    }
+
+    let results = request
+        .jobs
+        .iter()
+        .map(|job| create_job_result(job, &lookup_results))
+        .collect();
+    SymbolicationResponse { results }
+}
+
+/// The tasks which load the libraries of one request.
+///
+/// If the request is dropped while the tasks are still running, which
+/// happens when the client disconnects, the tasks are aborted. This cancels
+/// their pending symbol file downloads. Tasks which are still running when
+/// the deadline is reached are detached instead, so that they can finish
+/// their downloads.
+#[derive(Default)]
+struct LookupTasks(Vec<JoinHandle<()>>);
+
+impl LookupTasks {
+    fn detach(mut self) {
+        // Dropping a JoinHandle detaches its task.
+        self.0.clear();
+    }
+}
+
+impl Drop for LookupTasks {
+    fn drop(&mut self) {
+        if self.0.iter().any(|task| !task.is_finished()) {
+            tracing::info!("Symbolication canceled, the client has disconnected");
+        }
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+/// Collects the addresses to look up in each library. Libraries of the
+/// memory maps which no frame refers to are left out, so we don't load them.
+fn collect_addresses_per_module(
+    request: &SymbolicationRequest,
+) -> HashMap<&RequestModule, BTreeSet<u64>> {
+    let mut addresses_per_module: HashMap<&RequestModule, BTreeSet<u64>> = HashMap::new();
+    for job in &request.jobs {
+        for frame in job.stacks.iter().flatten() {
+            if let Some(module) = job.module_for_frame(frame) {
+                addresses_per_module
+                    .entry(module)
+                    .or_default()
+                    .insert(frame.module_offset);
+            }
+        }
+    }
+    addresses_per_module
+}
+
+fn timeout_error() -> ModuleError {
+    ModuleError {
+        name: "Timeout".into(),
+        message: "Symbols for this library were not loaded within the deadline".into(),
+    }
+}
+
+/// Looks up a single address in a single library, for `/lookup/v1`.
+pub async fn look_up_address(
+    symbol_manager: &SymbolManager,
+    permits: &LookupPermits,
+    module: &RequestModule,
+    module_offset: u64,
+) -> Result<LookupResponse, ModuleError> {
+    let addresses = BTreeSet::from([module_offset]);
+    let address_infos =
+        match look_up_module(symbol_manager, permits, module, &addresses, None).await {
+            ModuleLookupResult::Found(address_infos) => address_infos,
+            ModuleLookupResult::Error(error) => return Err(error),
+        };
+    let frame = RequestFrame {
+        module_index: Some(0),
+        module_offset,
+    };
+    let address_info = address_infos.get(&module_offset);
+    let response_frame = create_response_frame(0, &frame, Some(module), address_info);
+    Ok(LookupResponse {
+        debug_name: module.debug_name.clone(),
+        debug_id: module.debug_id.breakpad().to_string(),
+        module_offset,
+        found: address_info.is_some(),
+        function: response_frame.function,
+        function_offset: response_frame.function_offset,
+        function_size: response_frame.function_size,
+        file: response_frame.file,
+        line: response_frame.line,
+        inlines: response_frame.inlines,
+    })
+}
+
+/// Loads the symbols for `module`, from the local caches or from one of the
+/// configured servers, once one of the `permits` is free.
+pub async fn load_symbol_map(
+    symbol_manager: &SymbolManager,
+    permits: &LookupPermits,
+    module: &RequestModule,
+) -> Result<SymbolMap, ModuleError> {
+    load_symbol_map_until(symbol_manager, permits, module, None).await
+}
+
+/// Like [`load_symbol_map`], but gives up with a timeout error if no permit
+/// is free before `deadline`. Once loading has started, it isn't canceled.
+async fn load_symbol_map_until(
+    symbol_manager: &SymbolManager,
+    permits: &LookupPermits,
+    module: &RequestModule,
+    deadline: Option<Instant>,
+) -> Result<SymbolMap, ModuleError> {
+    let Some(_permit) = permits.acquire(deadline).await else {
+        return Err(timeout_error());
+    };
+    let library_info = LibraryInfo {
+        debug_name: Some(module.debug_name.clone()),
+        debug_id: Some(module.debug_id),
+        code_id: module.code_id.clone(),
+        ..Default::default()
+    };
+    symbol_manager
+        .load_symbol_map(&library_info)
+        .await
+        .map_err(|e| {
+            // If no candidate file could be found, or the server said it
+            // doesn't have the file, nobody has symbols for this library.
+            // Other download failures, e.g. timeouts or 5xx responses, may
+            // go away, and anything else, e.g. a file which couldn't be
+            // parsed, is our problem or the server's.
+            let name = match e {
+                wholesym::Error::NoCandidatePathForBinary(..)
+                | wholesym::Error::NoCandidatePathForDebugFile(..) => ModuleError::NOT_FOUND,
+                wholesym::Error::HelperErrorDuringOpenFile(..) if is_missing_on_server(&e) => {
+                    ModuleError::NOT_FOUND
+                }
+                wholesym::Error::HelperErrorDuringOpenFile(..) => ModuleError::DOWNLOAD_ERROR,
+                _ => "LoadError",
+            };
+            ModuleError {
+                name: name.into(),
+                message: e.to_string(),
+            }
+        })
+}
+
+/// Whether the last file couldn't be opened because its download got a 404.
+fn is_missing_on_server(error: &wholesym::Error) -> bool {
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        if let Some(wholesym::DownloadError::StatusError(404)) = error.downcast_ref() {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+async fn look_up_module(
+    symbol_manager: &SymbolManager,
+    permits: &LookupPermits,
+    module: &RequestModule,
+    addresses: &BTreeSet<u64>,
+    deadline: Option<Instant>,
+) -> ModuleLookupResult {
+    let symbol_map = match load_symbol_map_until(symbol_manager, permits, module, deadline).await {
+        Ok(symbol_map) => symbol_map,
+        Err(error) => return ModuleLookupResult::Error(error),
+    };
+
+    let mut address_infos = HashMap::new();
+    for &address in addresses {
+        let Ok(relative_address) = u32::try_from(address) else {
+            continue;
+        };
+        if let Some(info) = symbol_map
+            .lookup(LookupAddress::Relative(relative_address))
+            .await
+        {
+            address_infos.insert(address, info);
+        }
+    }
+    ModuleLookupResult::Found(address_infos)
+}
+
+fn create_job_result(
+    job: &SymbolicationJob,
+    lookup_results: &HashMap<RequestModule, ModuleLookupResult>,
+) -> SymbolicationJobResult {
+    let mut found_modules = BTreeMap::new();
+    let mut module_errors = BTreeMap::new();
+    for module in &job.memory_map {
+        let found = match lookup_results.get(module) {
+            Some(ModuleLookupResult::Found(_)) => Some(true),
+            Some(ModuleLookupResult::Error(error)) => {
+                module_errors.insert(module.key(), vec![error.clone()]);
+                Some(false)
+            }
+            None => None,
+        };
+        found_modules.insert(module.key(), found);
+    }
+
+    let stacks = job
+        .stacks
+        .iter()
+        .map(|stack| {
+            stack
+                .iter()
+                .enumerate()
+                .map(|(frame_index, frame)| {
+                    let module = job.module_for_frame(frame);
+                    let address_info = match module.and_then(|m| lookup_results.get(m)) {
+                        Some(ModuleLookupResult::Found(infos)) => infos.get(&frame.module_offset),
+                        _ => None,
+                    };
+                    create_response_frame(frame_index, frame, module, address_info)
+                })
+                .collect()
+        })
+        .collect();
+
+    SymbolicationJobResult {
+        stacks,
+        found_modules,
+        module_errors,
+    }
+}
+
+fn create_response_frame(
+    frame_index: usize,
+    frame: &RequestFrame,
+    module: Option<&RequestModule>,
+    address_info: Option<&AddressInfo>,
+) -> ResponseFrame {
+    let mut response_frame = ResponseFrame {
+        frame: frame_index,
+        module_offset: frame.module_offset,
+        module: module.map(|m| m.debug_name.clone()),
+        function: None,
+        function_offset: None,
+        function_size: None,
+        file: None,
+        line: None,
+        inlines: Vec::new(),
+    };
+    let Some(info) = address_info else {
+        return response_frame;
+    };
+
+    response_frame.function = Some(info.symbol.name.clone());
+    response_frame.function_offset = Some(
+        frame
+            .module_offset
+            .saturating_sub(u64::from(info.symbol.address)),
+    );
+    response_frame.function_size = info.symbol.size.map(u64::from);
+
+    // The debug info frames are ordered from innermost to outermost. The
+    // outermost frame is the function itself; all other frames are inlined
+    // calls.
+    if let Some((outer, inlines)) = info.frames.as_deref().and_then(|f| f.split_last()) {
+        response_frame.file = outer.file_path.as_ref().map(|p| p.display_path());
+        response_frame.line = outer.line_number;
+        response_frame.inlines = inlines
+            .iter()
+            .map(|inline| InlineFrame {
+                function: inline.function.clone(),
+                file: inline.file_path.as_ref().map(|p| p.display_path()),
+                line: inline.line_number,
+            })
+            .collect();
+    }
+    response_frame
+}
@@ -0,0 +1,263 @@
+use std::fmt;
+
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use wholesym::debugid::{CodeId, DebugId};
+
+/// A `/symbolicate/v5` request.
+///
+/// On the wire, this is either `{ "jobs": [job, ...] }` or a single job
+/// object. Both forms are normalized into a list of jobs.
+///
+/// The form is told apart by its keys while the request is parsed, so that
+/// the body is never buffered as a whole and errors name the bad field.
+#[derive(Debug)]
+pub struct SymbolicationRequest {
+    pub jobs: Vec<SymbolicationJob>,
+}
+
+impl<'de> Deserialize<'de> for SymbolicationRequest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(SymbolicationRequestVisitor)
+    }
+}
+
+struct SymbolicationRequestVisitor;
+
+impl<'de> Visitor<'de> for SymbolicationRequestVisitor {
+    type Value = SymbolicationRequest;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an object with either \"jobs\" or \"memoryMap\" and \"stacks\"")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut jobs = None;
+        let mut memory_map = None;
+        let mut stacks = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "jobs" if jobs.is_some() => return Err(de::Error::duplicate_field("jobs")),
+                "jobs" => jobs = Some(map.next_value()?),
+                "memoryMap" if memory_map.is_some() => {
+                    return Err(de::Error::duplicate_field("memoryMap"))
+                }
+                "memoryMap" => memory_map = Some(map.next_value()?),
+                "stacks" if stacks.is_some() => return Err(de::Error::duplicate_field("stacks")),
+                "stacks" => stacks = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        if let Some(jobs) = jobs {
+            if memory_map.is_some() || stacks.is_some() {
+                return Err(de::Error::custom(
+                    "\"jobs\" can't be combined with \"memoryMap\" or \"stacks\"",
+                ));
+            }
+            return Ok(SymbolicationRequest { jobs });
+        }
+        let job = SymbolicationJob {
+            memory_map: memory_map.ok_or_else(|| de::Error::missing_field("memoryMap"))?,
+            stacks: stacks.ok_or_else(|| de::Error::missing_field("stacks"))?,
+        };
+        Ok(SymbolicationRequest { jobs: vec![job] })
+    }
+}
+
+/// One job of a symbolication request: a list of libraries, and a list of
+/// stacks whose frames refer to those libraries by index.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolicationJob {
+    pub memory_map: Vec<RequestModule>,
+    pub stacks: Vec<Vec<RequestFrame>>,
+}
+
+impl SymbolicationJob {
+    /// The library that `frame` points into, if its module index is valid.
+    pub fn module_for_frame(&self, frame: &RequestFrame) -> Option<&RequestModule> {
+        self.memory_map.get(frame.module_index?)
+    }
+}
+
+/// A library in the memory map, serialized as `[debugName, breakpadId]` or
+/// `[debugName, breakpadId, codeId]`.
+///
+/// The code ID is optional. It's needed to find the symbols for Linux
+/// libraries on debuginfod servers, which look files up by their full ELF
+/// build ID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestModule {
+    pub debug_name: String,
+    pub debug_id: DebugId,
+    pub code_id: Option<CodeId>,
+}
+
+impl<'de> Deserialize<'de> for RequestModule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(RequestModuleVisitor)
+    }
+}
+
+struct RequestModuleVisitor;
+
+impl<'de> Visitor<'de> for RequestModuleVisitor {
+    type Value = RequestModule;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("[debugName, breakpadId] or [debugName, breakpadId, codeId]")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let debug_name: String = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let breakpad_id: String = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let code_id: Option<String> = seq.next_element()?;
+        if seq.next_element::<IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(4, &self));
+        }
+        let mut module =
+            RequestModule::try_from((debug_name, breakpad_id)).map_err(de::Error::custom)?;
+        if let Some(code_id) = code_id {
+            module.code_id = Some(code_id.parse().map_err(|_| {
+                de::Error::custom(format!(
+                    "Invalid code ID {code_id:?} for {}",
+                    module.debug_name
+                ))
+            })?);
+        }
+        Ok(module)
+    }
+}
+
+impl RequestModule {
+    /// The `debugName/BREAKPADID` key which identifies this library in the
+    /// response.
+    pub fn key(&self) -> String {
+        format!("{}/{}", self.debug_name, self.debug_id.breakpad())
+    }
+}
+
+impl TryFrom<(String, String)> for RequestModule {
+    type Error = String;
+
+    fn try_from((debug_name, breakpad_id): (String, String)) -> Result<Self, Self::Error> {
+        let debug_id = DebugId::from_breakpad(&breakpad_id)
+            .map_err(|_| format!("Invalid breakpad ID {breakpad_id:?} for {debug_name}"))?;
+        Ok(Self {
+            debug_name,
+            debug_id,
+            code_id: None,
+        })
+    }
+}
+
+/// A stack frame, serialized as `[moduleIndex, moduleOffset]`. A module
+/// index of -1 means that the address isn't in any known library.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(from = "(i64, u64)")]
+pub struct RequestFrame {
+    pub module_index: Option<usize>,
+    pub module_offset: u64,
+}
+
+impl From<(i64, u64)> for RequestFrame {
+    fn from((module_index, module_offset): (i64, u64)) -> Self {
+        Self {
+            module_index: usize::try_from(module_index).ok(),
+            module_offset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_jobs_request() {
+        let request: SymbolicationRequest = serde_json::from_str(
+            r#"{
+                "jobs": [{
+                    "memoryMap": [["xul.pdb", "44E4EC8C2F41492B9369D6B9A059577C2"]],
+                    "stacks": [[[0, 11723767], [-1, 1234]]]
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(request.jobs.len(), 1);
+        let job = &request.jobs[0];
+        assert_eq!(job.memory_map[0].debug_name, "xul.pdb");
+        assert_eq!(
+            job.memory_map[0].key(),
+            "xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2"
+        );
+        let frames = &job.stacks[0];
+        assert_eq!(frames[0].module_index, Some(0));
+        assert_eq!(frames[0].module_offset, 11723767);
+        assert_eq!(frames[1].module_index, None);
+        assert!(job.module_for_frame(&frames[0]).is_some());
+        assert!(job.module_for_frame(&frames[1]).is_none());
+    }
+
+    #[test]
+    fn test_parse_single_job_request() {
+        let request: SymbolicationRequest = serde_json::from_str(
+            r#"{
+                "memoryMap": [["libxul.so", "F1B1B2A7E4B0B51B1DA4B4E5A2C0D51D0"]],
+                "stacks": [[[0, 4096]]]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(request.jobs.len(), 1);
+        assert_eq!(request.jobs[0].memory_map[0].debug_name, "libxul.so");
+    }
+
+    #[test]
+    fn test_parse_module_with_code_id() {
+        let request: SymbolicationRequest = serde_json::from_str(
+            r#"{
+                "memoryMap": [[
+                    "libxul.so",
+                    "F1B1B2A7E4B0B51B1DA4B4E5A2C0D51D0",
+                    "a7b2b1f1b0e41bb51da4b4e5a2c0d51d12345678"
+                ]],
+                "stacks": []
+            }"#,
+        )
+        .unwrap();
+        let module = &request.jobs[0].memory_map[0];
+        assert_eq!(
+            module.code_id.as_ref().map(ToString::to_string).as_deref(),
+            Some("a7b2b1f1b0e41bb51da4b4e5a2c0d51d12345678")
+        );
+    }
+
+    #[test]
+    fn test_reject_invalid_breakpad_id() {
+        let result = serde_json::from_str::<SymbolicationRequest>(
+            r#"{ "memoryMap": [["xul.pdb", "not-a-breakpad-id"]], "stacks": [] }"#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_errors_name_the_bad_field() {
+        let error = serde_json::from_str::<SymbolicationRequest>(r#"{ "memoryMap": [] }"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("missing field `stacks`"), "{error}");
+
+        let error = serde_json::from_str::<SymbolicationRequest>(
+            r#"{ "jobs": [{ "memoryMap": [["xul.pdb"]], "stacks": [] }] }"#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("invalid length 1"), "{error}");
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Serializer};
+
+/// A `/symbolicate/v5` response, with one result per request job.
+#[derive(Debug, Serialize)]
+pub struct SymbolicationResponse {
+    pub results: Vec<SymbolicationJobResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SymbolicationJobResult {
+    /// The symbolicated stacks, in the same order as the request stacks.
+    pub stacks: Vec<Vec<ResponseFrame>>,
+    /// Maps each `debugName/BREAKPADID` of the memory map to whether symbols
+    /// were found for it, or to `null` if no frame refers to it and we didn't
+    /// look it up, like Tecken does.
+    pub found_modules: BTreeMap<String, Option<bool>>,
+    /// The errors we encountered when looking up symbols for each library.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub module_errors: BTreeMap<String, Vec<ModuleError>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseFrame {
+    /// The index of this frame in its stack.
+    pub frame: usize,
+    #[serde(serialize_with = "as_hex")]
+    pub module_offset: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "as_optional_hex"
+    )]
+    pub function_offset: Option<u64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "as_optional_hex"
+    )]
+    pub function_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// The inlined calls at this address, innermost first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inlines: Vec<InlineFrame>,
+}
+
+/// A `/lookup/v1` response, describing a single address.
+#[derive(Debug, Serialize)]
+pub struct LookupResponse {
+    pub debug_name: String,
+    pub debug_id: String,
+    #[serde(serialize_with = "as_hex")]
+    pub module_offset: u64,
+    /// Whether the address was covered by a symbol.
+    pub found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "as_optional_hex"
+    )]
+    pub function_offset: Option<u64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "as_optional_hex"
+    )]
+    pub function_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// The inlined calls at this address, innermost first.
+    pub inlines: Vec<InlineFrame>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InlineFrame {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleError {
+    /// A machine-readable error kind, e.g. `"NotFound"`.
+    pub name: String,
+    pub message: String,
+}
+
+impl ModuleError {
+    /// The kind of error for libraries which none of the symbol sources have.
+    pub const NOT_FOUND: &'static str = "NotFound";
+
+    /// The kind of error for libraries whose symbol file couldn't be
+    /// downloaded, e.g. because the server timed out or failed. Asking again
+    /// later may work.
+    pub const DOWNLOAD_ERROR: &'static str = "DownloadError";
+
+    pub fn is_not_found(&self) -> bool {
+        self.name == Self::NOT_FOUND
+    }
+
+    pub fn is_download_error(&self) -> bool {
+        self.name == Self::DOWNLOAD_ERROR
+    }
+}
+
+fn as_hex<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&format_args!("{value:#x}"))
+}
+
+fn as_optional_hex<S: Serializer>(value: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => as_hex(value, serializer),
+        None => serializer.serialize_none(),
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+use wholesym::SymbolManager;
+
+use super::{load_symbol_map, LookupPermits, ModuleError, RequestModule};
+
+/// All symbols of a library, sorted by address.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolTable {
+    pub debug_name: String,
+    pub debug_id: String,
+    pub symbols: Vec<SymbolTableEntry>,
+}
+
+/// A symbol, serialized as `[address, size, name]`.
+///
+/// Symbol files don't always store symbol sizes, so the size is the distance
+/// to the next symbol's address. It's `None` for the last symbol.
+#[derive(Debug, Serialize)]
+pub struct SymbolTableEntry(pub u32, pub Option<u32>, pub String);
+
+/// Loads the symbol table of `module`.
+pub async fn load_symbol_table(
+    symbol_manager: &SymbolManager,
+    permits: &LookupPermits,
+    module: &RequestModule,
+) -> Result<SymbolTable, ModuleError> {
+    let symbol_map = load_symbol_map(symbol_manager, permits, module).await?;
+    let mut symbols: Vec<(u32, String)> = symbol_map
+        .iter_symbols()
+        .map(|(address, name)| (address, name.into_owned()))
+        .collect();
+    drop(symbol_map);
+    symbols.sort_by_key(|(address, _)| *address);
+    symbols.dedup_by_key(|(address, _)| *address);
+
+    let next_addresses = symbols.iter().skip(1).map(|(address, _)| Some(*address));
+    let sizes: Vec<Option<u32>> = next_addresses.chain([None]).collect();
+    let symbols = symbols
+        .into_iter()
+        .zip(sizes)
+        .map(|((address, name), next_address)| {
+            SymbolTableEntry(address, next_address.map(|next| next - address), name)
+        })
+        .collect();
+
+    Ok(SymbolTable {
+        debug_name: module.debug_name.clone(),
+        debug_id: module.debug_id.breakpad().to_string(),
+        symbols,
+    })
+}
+
+impl SymbolTable {
+    /// Writes the table in the compact binary format, in which all integers
+    /// are little-endian `u32`s:
+    ///
+    /// - the number of symbols `n`,
+    /// - `n` symbol addresses, in ascending order,
+    /// - `n + 1` offsets into the name buffer; the name of symbol `i` is
+    ///   `buffer[offsets[i]..offsets[i + 1]]`,
+    /// - the name buffer, containing the concatenated UTF-8 symbol names.
+    ///
+    /// Symbol sizes are implied by the distance between addresses.
+    pub fn write_compact(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let count = u32::try_from(self.symbols.len()).map_err(io::Error::other)?;
+        writer.write_all(&count.to_le_bytes())?;
+        for SymbolTableEntry(address, _, _) in &self.symbols {
+            writer.write_all(&address.to_le_bytes())?;
+        }
+        let mut offset: u32 = 0;
+        writer.write_all(&offset.to_le_bytes())?;
+        for SymbolTableEntry(_, _, name) in &self.symbols {
+            let len = u32::try_from(name.len()).map_err(io::Error::other)?;
+            offset = offset.checked_add(len).ok_or_else(|| {
+                io::Error::other("Symbol names exceed the compact format's 4 GB limit")
+            })?;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        for SymbolTableEntry(_, _, name) in &self.symbols {
+            writer.write_all(name.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_compact() {
+        let table = SymbolTable {
+            debug_name: "test.pdb".into(),
+            debug_id: "00000000000000000000000000000000".into(),
+            symbols: vec![
+                SymbolTableEntry(0x10, Some(0x10), "ab".into()),
+                SymbolTableEntry(0x20, None, "c".into()),
+            ],
+        };
+        let mut bytes = Vec::new();
+        table.write_compact(&mut bytes).unwrap();
+
+        let expected: Vec<u8> = [2u32, 0x10, 0x20, 0, 2, 3]
+            .iter()
+            .flat_map(|n| n.to_le_bytes())
+            .chain(*b"abc")
+            .collect();
+        assert_eq!(bytes, expected);
+    }
+}
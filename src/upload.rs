@@ -0,0 +1,394 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use wholesym::debugid::DebugId;
+
+use crate::auth::AccessTokens;
+use crate::configuration::UploadSettings;
+use crate::error::ApiError;
+use crate::quota::QuotaNotifiers;
+
+/// The default maximum size of an upload, if none is configured in
+/// `[upload]`.
+pub const DEFAULT_MAX_UPLOAD_SIZE: usize = 2 * 1000 * 1000 * 1000; // 2 GB
+
+/// Uploads are staged in this subdirectory of the upload directory, so that
+/// they can be moved into place with a rename.
+const STAGING_DIR_NAME: &str = ".staging";
+
+/// The longest `MODULE` line we accept.
+const MAX_HEADER_LINE_LEN: u64 = 4096;
+
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Stores uploaded Breakpad symbol files in the upload directory.
+pub struct SymbolUploader {
+    dir: PathBuf,
+    tokens: AccessTokens,
+    max_size: usize,
+    quota_manager_notifier: Option<QuotaNotifiers>,
+}
+
+impl SymbolUploader {
+    /// Fails if one of the tokens can't be read.
+    pub fn new(
+        settings: UploadSettings,
+        quota_manager_notifier: Option<QuotaNotifiers>,
+    ) -> Result<Self, String> {
+        if settings.tokens.is_empty() {
+            tracing::warn!("No upload tokens configured, all uploads will be rejected.");
+        }
+        let tokens = AccessTokens::read(&settings.tokens)
+            .map_err(|e| format!("Could not read the upload tokens: {e}"))?;
+        Ok(Self {
+            dir: settings.dir,
+            tokens,
+            max_size: settings.max_size.map_or(DEFAULT_MAX_UPLOAD_SIZE, |size| {
+                usize::try_from(size).unwrap_or(usize::MAX)
+            }),
+            quota_manager_notifier: if settings.exempt_from_eviction {
+                None
+            } else {
+                quota_manager_notifier
+            },
+        })
+    }
+
+    /// The maximum size of an upload, compressed or not.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// The tokens which are allowed to upload.
+    pub fn tokens(&self) -> &AccessTokens {
+        &self.tokens
+    }
+
+    /// Copies the upload from `reader` into a staging file, extracts and
+    /// validates all symbol files in it, and then moves them into place.
+    /// Returns the paths of the stored files, relative to the upload
+    /// directory.
+    ///
+    /// This does blocking I/O.
+    pub fn store_upload(&self, reader: &mut dyn Read) -> Result<Vec<String>, ApiError> {
+        let staging_dir = self.dir.join(STAGING_DIR_NAME);
+        std::fs::create_dir_all(&staging_dir)?;
+        let mut staged_files = StagedFiles::new(staging_dir);
+
+        let (upload_path, mut upload) = staged_files.create()?;
+        copy_upload(reader, &mut upload)?;
+        upload.seek(SeekFrom::Start(0))?;
+
+        let mut extractor = Extractor {
+            staged_files: &mut staged_files,
+            remaining_size: self.max_size,
+            files: Vec::new(),
+        };
+        match UploadFormat::detect(&mut upload)? {
+            UploadFormat::Sym => extractor.add_file(None, &mut upload)?,
+            UploadFormat::Zip => extractor.add_zip(upload)?,
+            UploadFormat::TarGz => {
+                extractor.add_tar(tar::Archive::new(flate2::read::GzDecoder::new(upload)))?
+            }
+            UploadFormat::Tar => extractor.add_tar(tar::Archive::new(upload))?,
+        }
+        let files = extractor.files;
+        staged_files.remove(&upload_path);
+        if files.is_empty() {
+            return Err(ApiError::InvalidUpload(
+                "The upload contains no symbol files".into(),
+            ));
+        }
+
+        let mut stored = Vec::with_capacity(files.len());
+        for (staged_path, relative_path) in files {
+            let final_path = self.dir.join(&relative_path);
+            if let Some(parent) = final_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::rename(&staged_path, &final_path)?;
+            staged_files.forget(&staged_path);
+            let size = std::fs::metadata(&final_path)?.len();
+            tracing::info!(
+                path = final_path.to_string_lossy().to_string(),
+                size_in_bytes = size,
+                "Stored uploaded symbol file"
+            );
+            if let Some(notifier) = &self.quota_manager_notifier {
+                notifier.on_file_created(&final_path, size, SystemTime::now());
+            }
+            stored.push(relative_path.to_string_lossy().replace('\\', "/"));
+        }
+        if let Some(notifier) = &self.quota_manager_notifier {
+            notifier.trigger_eviction_if_needed();
+        }
+        Ok(stored)
+    }
+}
+
+enum UploadFormat {
+    Sym,
+    Zip,
+    TarGz,
+    Tar,
+}
+
+impl UploadFormat {
+    fn detect(file: &mut File) -> Result<Self, ApiError> {
+        let mut magic = Vec::with_capacity(512);
+        Read::by_ref(file).take(512).read_to_end(&mut magic)?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(if magic.starts_with(b"MODULE ") {
+            UploadFormat::Sym
+        } else if magic.starts_with(b"PK\x03\x04") {
+            UploadFormat::Zip
+        } else if magic.starts_with(&[0x1f, 0x8b]) {
+            UploadFormat::TarGz
+        } else if magic.get(257..262) == Some(b"ustar") {
+            UploadFormat::Tar
+        } else {
+            return Err(ApiError::InvalidUpload(
+                "Expected a .sym file, or a zip, tar or tar.gz archive".into(),
+            ));
+        })
+    }
+}
+
+/// Copies the symbol files out of an upload into staging files.
+struct Extractor<'a> {
+    staged_files: &'a mut StagedFiles,
+    /// How many more bytes we accept, across all files.
+    remaining_size: usize,
+    /// The staged files and their paths relative to the upload directory.
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Extractor<'_> {
+    fn add_zip(&mut self, file: File) -> Result<(), ApiError> {
+        let mut archive = zip::ZipArchive::new(file).map_err(invalid_archive)?;
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index).map_err(invalid_archive)?;
+            if entry.is_dir() {
+                continue;
+            }
+            let entry_path = entry.enclosed_name().ok_or_else(|| {
+                ApiError::InvalidUpload(format!("Invalid path {:?} in archive", entry.name()))
+            })?;
+            self.add_file(Some(&entry_path), &mut entry)?;
+        }
+        Ok(())
+    }
+
+    fn add_tar<R: Read>(&mut self, mut archive: tar::Archive<R>) -> Result<(), ApiError> {
+        for entry in archive.entries().map_err(invalid_archive)? {
+            let mut entry = entry.map_err(invalid_archive)?;
+            match entry.header().entry_type() {
+                tar::EntryType::Regular => {}
+                tar::EntryType::Directory => continue,
+                other => {
+                    return Err(ApiError::InvalidUpload(format!(
+                        "Unsupported archive entry type {other:?}"
+                    )))
+                }
+            }
+            let entry_path = entry.path().map_err(invalid_archive)?.into_owned();
+            self.add_file(Some(&entry_path), &mut entry)?;
+        }
+        Ok(())
+    }
+
+    /// Stages one symbol file. If the file came from an archive, its path in
+    /// the archive must match its `MODULE` header.
+    fn add_file(
+        &mut self,
+        entry_path: Option<&Path>,
+        reader: &mut dyn Read,
+    ) -> Result<(), ApiError> {
+        let mut reader = BufReader::new(reader);
+        let mut header = String::new();
+        reader
+            .by_ref()
+            .take(MAX_HEADER_LINE_LEN)
+            .read_line(&mut header)
+            .map_err(|e| ApiError::InvalidUpload(format!("Could not read MODULE line: {e}")))?;
+        if !header.ends_with('\n') {
+            return Err(ApiError::InvalidUpload(
+                "The MODULE line is missing or too long".into(),
+            ));
+        }
+        let relative_path = symbol_file_path(&header)?;
+        if let Some(entry_path) = entry_path {
+            if !is_valid_entry_path(entry_path, &relative_path) {
+                return Err(ApiError::InvalidUpload(format!(
+                    "Archive entry {entry_path:?} should be at {relative_path:?} according to its MODULE line"
+                )));
+            }
+        }
+
+        let (staged_path, mut file) = self.staged_files.create()?;
+        file.write_all(header.as_bytes())?;
+        let limit = self.remaining_size.saturating_sub(header.len());
+        let copied = copy_limited(&mut reader, &mut file, limit)?;
+        self.remaining_size = limit - copied;
+        self.files.push((staged_path, relative_path));
+        Ok(())
+    }
+}
+
+/// Returns the path of a symbol file in the symbol server layout, based on
+/// its `MODULE <os> <arch> <breakpadId> <debugName>` line, e.g.
+/// `xul.pdb/<breakpadId>/xul.sym`.
+fn symbol_file_path(header: &str) -> Result<PathBuf, ApiError> {
+    let invalid = || {
+        ApiError::InvalidUpload(format!(
+            "Expected a MODULE line, got {:?}",
+            header.trim_end()
+        ))
+    };
+    let mut fields = header.trim_end().splitn(5, ' ');
+    let (Some("MODULE"), Some(_os), Some(_arch), Some(breakpad_id), Some(debug_name)) = (
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+    ) else {
+        return Err(invalid());
+    };
+    let debug_id = DebugId::from_breakpad(breakpad_id).map_err(|_| invalid())?;
+    let is_safe_name = !debug_name.is_empty()
+        && debug_name != "."
+        && debug_name != ".."
+        && !debug_name.contains(['/', '\\', '\0']);
+    if !is_safe_name {
+        return Err(invalid());
+    }
+    let stem = debug_name.strip_suffix(".pdb").unwrap_or(debug_name);
+    Ok([
+        debug_name.to_owned(),
+        debug_id.breakpad().to_string(),
+        format!("{stem}.sym"),
+    ]
+    .iter()
+    .collect())
+}
+
+/// Archive entries may be nested in a directory, but must end with the
+/// expected `<debugName>/<breakpadId>/<file>.sym` path.
+fn is_valid_entry_path(entry_path: &Path, expected: &Path) -> bool {
+    entry_path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+        && entry_path.ends_with(expected)
+}
+
+fn invalid_archive(e: impl std::fmt::Display) -> ApiError {
+    ApiError::InvalidUpload(format!("Could not read the archive: {e}"))
+}
+
+/// Copies the request body into `file`. Errors from `reader` are caused by the
+/// request body, and errors from `file` are ours.
+fn copy_upload(reader: &mut dyn Read, file: &mut File) -> Result<(), ApiError> {
+    let mut buffer = vec![0; COPY_BUFFER_SIZE];
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(ApiError::InvalidContentEncoding(e)),
+        };
+        file.write_all(&buffer[..len])?;
+    }
+}
+
+fn copy_limited(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    limit: usize,
+) -> Result<usize, ApiError> {
+    // Read one byte past the limit so that we can tell whether it was exceeded.
+    let copied = io::copy(&mut reader.take(limit as u64 + 1), writer)
+        .map_err(|e| ApiError::InvalidUpload(format!("Could not extract file: {e}")))?;
+    let copied = usize::try_from(copied).unwrap_or(usize::MAX);
+    if copied > limit {
+        return Err(ApiError::PayloadTooLarge { limit });
+    }
+    Ok(copied)
+}
+
+/// Files in the staging directory, which are deleted on drop unless they've
+/// been moved into place.
+struct StagedFiles {
+    dir: PathBuf,
+    paths: Vec<PathBuf>,
+}
+
+impl StagedFiles {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            paths: Vec::new(),
+        }
+    }
+
+    fn create(&mut self) -> io::Result<(PathBuf, File)> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let path = self.dir.join(format!(
+            "{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        self.paths.push(path.clone());
+        Ok((path, file))
+    }
+
+    fn remove(&mut self, path: &Path) {
+        let _ = std::fs::remove_file(path);
+        self.forget(path);
+    }
+
+    fn forget(&mut self, path: &Path) {
+        self.paths.retain(|p| p != path);
+    }
+}
+
+impl Drop for StagedFiles {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_file_path() {
+        assert_eq!(
+            symbol_file_path("MODULE windows x86_64 44E4EC8C2F41492B9369D6B9A059577C2 xul.pdb\n")
+                .unwrap(),
+            Path::new("xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2/xul.sym")
+        );
+        assert_eq!(
+            symbol_file_path("MODULE Linux x86_64 83CA53B0E8272691CEFCD79178D33D5C0 libxul.so\n")
+                .unwrap(),
+            Path::new("libxul.so/83CA53B0E8272691CEFCD79178D33D5C0/libxul.so.sym")
+        );
+        assert!(symbol_file_path("MODULE Linux x86_64 nothex libxul.so\n").is_err());
+        assert!(
+            symbol_file_path("MODULE Linux x86_64 83CA53B0E8272691CEFCD79178D33D5C0 ..\n").is_err()
+        );
+        assert!(symbol_file_path("FILE 0 main.c\n").is_err());
+    }
+}
@@ -0,0 +1,632 @@
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{
+    dev::Server,
+    http::{header, StatusCode},
+    web, App, HttpRequest, HttpResponse, HttpServer,
+};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::configuration::{Secret, Settings, SymbolServerSettings};
+use crate::error::ApiError;
+use crate::negative_cache::{MissingFile, NegativeCache};
+
+/// The delay before the first retry. It doubles with every further retry.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The response headers which are passed on to wholesym.
+const FORWARDED_RESPONSE_HEADERS: [reqwest::header::HeaderName; 5] = [
+    reqwest::header::CONTENT_TYPE,
+    reqwest::header::CONTENT_LENGTH,
+    reqwest::header::CONTENT_ENCODING,
+    reqwest::header::ETAG,
+    reqwest::header::LAST_MODIFIED,
+];
+
+/// A proxy on localhost for the symbol servers which need request headers,
+/// timeouts or retries, or for all Breakpad and Windows servers if the
+/// negative cache is enabled, because the proxy is what checks it.
+///
+/// wholesym only knows server URLs, so we configure it with the proxy's URL
+/// for each of these servers instead, and the proxy makes the actual requests
+/// with that server's settings.
+///
+/// The proxy attaches the servers' credentials, so other processes on this
+/// machine must not be able to use it. Its URLs start with a random secret
+/// which is only known to this process, and it only forwards requests for
+/// `<debugName>/<debugId>/<fileName>` paths.
+#[derive(Clone)]
+pub struct UpstreamProxy {
+    /// Maps the configured URL of a server to the URL that wholesym uses.
+    proxied_urls: HashMap<String, String>,
+}
+
+struct Upstream {
+    /// The URL as configured, which identifies the server in the negative
+    /// cache.
+    url: String,
+    base_url: String,
+    client: reqwest::Client,
+    credentials: Option<Credentials>,
+    retries: u32,
+}
+
+enum Credentials {
+    Bearer(String),
+    Basic { username: String, password: String },
+}
+
+impl UpstreamProxy {
+    /// Starts the proxy if any of the configured servers need it. The
+    /// returned server needs to be spawned, and stopped after the main
+    /// server, because it doesn't handle shutdown signals itself.
+    ///
+    /// Returns an `InvalidInput` error if a server's secrets can't be read or
+    /// its headers are invalid.
+    pub fn start(
+        settings: &Settings,
+        negative_cache: Option<Arc<NegativeCache>>,
+    ) -> std::io::Result<(Self, Option<Server>)> {
+        let mut servers: Vec<&SymbolServerSettings> = Vec::new();
+        if let Some(symbols) = &settings.symbols {
+            if let Some(breakpad) = &symbols.breakpad {
+                servers.extend(&breakpad.servers);
+            }
+            if let Some(windows) = &symbols.windows {
+                servers.extend(&windows.servers);
+            }
+        }
+        servers.retain(|server| negative_cache.is_some() || server.needs_proxy());
+        if servers.is_empty() {
+            return Ok((
+                Self {
+                    proxied_urls: HashMap::new(),
+                },
+                None,
+            ));
+        }
+
+        let mut upstreams = Vec::new();
+        for server in &servers {
+            upstreams.push(Upstream {
+                url: server.url.clone(),
+                base_url: server.url.trim_end_matches('/').to_owned(),
+                client: create_client(server).map_err(invalid_config)?,
+                credentials: read_credentials(server).map_err(invalid_config)?,
+                retries: server.retries,
+            });
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let secret = ProxySecret::generate()?;
+        let mut proxied_urls = HashMap::new();
+        for (index, server) in servers.into_iter().enumerate() {
+            // Don't log the proxied URL, it contains the secret.
+            tracing::info!(url = server.url, index, "Proxying symbol server");
+            proxied_urls.insert(
+                server.url.clone(),
+                format!("http://{address}/{}/{index}/", secret.0),
+            );
+        }
+
+        let upstreams = web::Data::new(upstreams);
+        let negative_cache = web::Data::new(negative_cache);
+        let secret = web::Data::new(secret);
+        let server = HttpServer::new(move || {
+            App::new()
+                .route(
+                    "/{secret}/{index}/{debug_name}/{debug_id}/{file_name}",
+                    web::get().to(proxy),
+                )
+                .app_data(upstreams.clone())
+                .app_data(negative_cache.clone())
+                .app_data(secret.clone())
+        })
+        .workers(2)
+        // The main server stops the proxy once its own requests are done,
+        // which may still need the proxy after a shutdown signal.
+        .disable_signals()
+        .listen(listener)?
+        .run();
+        Ok((Self { proxied_urls }, Some(server)))
+    }
+
+    /// The URL which wholesym should use for `server`.
+    pub fn url_for(&self, server: &SymbolServerSettings) -> String {
+        self.proxied_urls
+            .get(&server.url)
+            .cloned()
+            .unwrap_or_else(|| server.url.clone())
+    }
+
+    /// Maps a URL which wholesym requested back to the URL on the actual
+    /// server, for logging and metrics.
+    pub fn original_url(&self, url: &str) -> String {
+        for (server_url, proxied_url) in &self.proxied_urls {
+            if let Some(path) = url.strip_prefix(proxied_url.as_str()) {
+                return format!("{}/{path}", server_url.trim_end_matches('/'));
+            }
+        }
+        url.to_owned()
+    }
+}
+
+/// The random first path segment of all proxy URLs.
+struct ProxySecret(String);
+
+impl ProxySecret {
+    fn generate() -> std::io::Result<Self> {
+        let mut bytes = [0u8; 16];
+        getrandom::fill(&mut bytes).map_err(std::io::Error::other)?;
+        Ok(Self(bytes.iter().map(|b| format!("{b:02x}")).collect()))
+    }
+
+    /// Compares in constant time, so that the secret can't be guessed byte by
+    /// byte from the response times.
+    fn matches(&self, candidate: &str) -> bool {
+        self.0.len() == candidate.len()
+            && self
+                .0
+                .bytes()
+                .zip(candidate.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+fn invalid_config(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+fn create_client(server: &SymbolServerSettings) -> Result<reqwest::Client, String> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &server.headers {
+        let name = HeaderName::try_from(name.as_str())
+            .map_err(|e| format!("Invalid header name {name:?} for {}: {e}", server.url))?;
+        let value = HeaderValue::try_from(value.as_str())
+            .map_err(|e| format!("Invalid value for header {name} for {}: {e}", server.url))?;
+        headers.insert(name, value);
+    }
+    let mut builder = reqwest::Client::builder().default_headers(headers);
+    if let Some(timeout) = server.timeout {
+        // Not `timeout`, which would limit the whole download, and large
+        // files can take much longer than that.
+        builder = builder.connect_timeout(timeout).read_timeout(timeout);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Could not create HTTP client for {}: {e}", server.url))
+}
+
+/// The owned parts of a [`MissingFile`], so that they can be moved to a
+/// blocking task.
+#[derive(Clone)]
+struct NegativeCacheKey {
+    server: String,
+    debug_name: String,
+    debug_id: String,
+    file_name: String,
+}
+
+impl NegativeCacheKey {
+    fn as_missing_file(&self) -> MissingFile<'_> {
+        MissingFile {
+            server: &self.server,
+            debug_name: &self.debug_name,
+            debug_id: &self.debug_id,
+            file_name: &self.file_name,
+        }
+    }
+}
+
+fn read_credentials(server: &SymbolServerSettings) -> Result<Option<Credentials>, String> {
+    let read_secret = |secret: &Secret| {
+        secret
+            .read()
+            .map_err(|e| format!("Could not read the credentials for {}: {e}", server.url))
+    };
+    if let Some(token) = &server.bearer_token {
+        return Ok(Some(Credentials::Bearer(read_secret(token)?)));
+    }
+    server
+        .basic_auth
+        .as_ref()
+        .map(|basic_auth| {
+            Ok(Credentials::Basic {
+                username: basic_auth.username.clone(),
+                password: read_secret(&basic_auth.password)?,
+            })
+        })
+        .transpose()
+}
+
+/// Whether `segment` of a proxied path can be passed on to the server as is,
+/// so that requests can't reach other paths on the server.
+fn is_valid_path_segment(segment: &str) -> bool {
+    !segment.is_empty() && segment != "." && segment != ".." && !segment.contains(['/', '\\'])
+}
+
+async fn proxy(
+    req: HttpRequest,
+    path: web::Path<(String, usize, String, String, String)>,
+    upstreams: web::Data<Vec<Upstream>>,
+    negative_cache: web::Data<Option<Arc<NegativeCache>>>,
+    secret: web::Data<ProxySecret>,
+) -> Result<HttpResponse, ApiError> {
+    let (request_secret, index, debug_name, debug_id, file_name) = path.into_inner();
+    if !secret.matches(&request_secret) {
+        return Err(ApiError::NotFound("Not found".to_owned()));
+    }
+    let upstream = upstreams
+        .get(index)
+        .ok_or_else(|| ApiError::NotFound(format!("No upstream server {index}")))?;
+    if !req.query_string().is_empty()
+        || ![&debug_name, &debug_id, &file_name]
+            .iter()
+            .all(|segment| is_valid_path_segment(segment))
+    {
+        return Err(ApiError::InvalidPath(format!(
+            "Invalid symbol file path {}",
+            req.path()
+        )));
+    }
+    // Use the raw path, so that percent-encoded characters are passed on
+    // unchanged.
+    let prefix_len = format!("/{request_secret}/{index}").len();
+    let path = req.uri().path().get(prefix_len..).unwrap_or_default();
+    let url = format!("{}{path}", upstream.base_url);
+
+    let negative_cache = negative_cache.get_ref().clone().map(|cache| {
+        let key = NegativeCacheKey {
+            server: upstream.url.clone(),
+            debug_name,
+            debug_id,
+            file_name,
+        };
+        (cache, key)
+    });
+    if let Some((cache, key)) = negative_cache.clone() {
+        let is_missing = web::block(move || cache.contains(&key.as_missing_file()))
+            .await
+            .map_err(|e| ApiError::Internal(e.to_string()))?;
+        match is_missing {
+            Ok(true) => {
+                tracing::info!(
+                    url,
+                    "Skipping request, the server didn't have this file before"
+                );
+                return Ok(HttpResponse::NotFound().finish());
+            }
+            Ok(false) => {}
+            Err(e) => tracing::error!(error = %e, "Could not query the negative cache"),
+        }
+    }
+
+    let mut attempt = 0;
+    let response = loop {
+        let mut request = upstream.client.get(&url);
+        request = match &upstream.credentials {
+            Some(Credentials::Bearer(token)) => request.bearer_auth(token),
+            Some(Credentials::Basic { username, password }) => {
+                request.basic_auth(username, Some(password))
+            }
+            None => request,
+        };
+        if let Some(accept_encoding) = req.headers().get(header::ACCEPT_ENCODING) {
+            request = request.header(reqwest::header::ACCEPT_ENCODING, accept_encoding.as_bytes());
+        }
+        let result = request.send().await;
+        let should_retry = match &result {
+            Ok(response) => {
+                response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || response.status().is_server_error()
+            }
+            Err(e) => e.is_connect() || e.is_timeout(),
+        };
+        if !should_retry || attempt >= upstream.retries {
+            break result;
+        }
+        let delay = INITIAL_RETRY_DELAY * 2u32.saturating_pow(attempt);
+        attempt += 1;
+        tracing::info!(url, attempt, ?delay, "Retrying symbol server request");
+        tokio::time::sleep(delay).await;
+    };
+
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!(url, error = %e, "Symbol server request failed");
+            let status = if e.is_timeout() {
+                StatusCode::GATEWAY_TIMEOUT
+            } else {
+                StatusCode::BAD_GATEWAY
+            };
+            return Ok(HttpResponse::build(status).finish());
+        }
+    };
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        if let Some((cache, key)) = negative_cache {
+            let result = web::block(move || cache.insert(&key.as_missing_file())).await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::error!(error = %e, "Could not update the negative cache"),
+                Err(e) => tracing::error!(error = %e, "Could not update the negative cache"),
+            }
+        }
+    }
+
+    let status = StatusCode::from_u16(response.status().as_u16())
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    let mut builder = HttpResponse::build(status);
+    for name in &FORWARDED_RESPONSE_HEADERS {
+        if let Some(value) = response.headers().get(name) {
+            builder.insert_header((name.as_str(), value.as_bytes()));
+        }
+    }
+    Ok(builder.streaming(response.bytes_stream()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// A stand-in symbol server which fails the first request with a 503,
+    /// and records the request headers of all requests.
+    fn spawn_flaky_server() -> (String, Arc<Mutex<Vec<Vec<String>>>>) {
+        spawn_server(|index| {
+            if index == 0 {
+                b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            } else {
+                b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\nMODULE\n"
+            }
+        })
+    }
+
+    /// A stand-in symbol server which sends the response for the n-th
+    /// request, and records the request headers of all requests.
+    fn spawn_server(respond: fn(usize) -> &'static [u8]) -> (String, Arc<Mutex<Vec<Vec<String>>>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded_requests = Arc::clone(&requests);
+        std::thread::spawn(move || {
+            for (index, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut lines = Vec::new();
+                let mut reader = BufReader::new(&stream);
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    lines.push(line.trim_end().to_owned());
+                }
+                recorded_requests.lock().unwrap().push(lines);
+                let _ = stream.write_all(respond(index));
+            }
+        });
+        (format!("http://{address}/symbols/"), requests)
+    }
+
+    #[tokio::test]
+    async fn test_proxy_adds_credentials_and_retries() {
+        let (server_url, requests) = spawn_flaky_server();
+        let token_file = std::env::temp_dir().join("reliost-test-upstream-proxy-token");
+        std::fs::write(&token_file, "secret-token\n").unwrap();
+
+        let settings: Settings = config::Config::builder()
+            .add_source(config::File::from_str(
+                &format!(
+                    r#"
+                    [server]
+                    host = "127.0.0.1"
+                    port = 0
+
+                    [symbols.breakpad]
+                    servers = [
+                        "https://symbols.example.com/",
+                        {{ url = "{server_url}", bearer_token = {{ file = "{}" }}, headers = {{ X-Client = "reliost" }}, retries = 1 }},
+                    ]
+                    cache_dir = "cache"
+                    "#,
+                    token_file.display()
+                ),
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        let servers = &settings
+            .symbols
+            .as_ref()
+            .unwrap()
+            .breakpad
+            .as_ref()
+            .unwrap()
+            .servers;
+
+        let (proxy, server) = UpstreamProxy::start(&settings, None).unwrap();
+        tokio::spawn(server.unwrap());
+        assert_eq!(proxy.url_for(&servers[0]), "https://symbols.example.com/");
+        let proxied_url = proxy.url_for(&servers[1]);
+        assert_ne!(proxied_url, server_url);
+        assert_eq!(
+            proxy.original_url(&format!("{proxied_url}xul.pdb/ID/xul.sym")),
+            format!("{server_url}xul.pdb/ID/xul.sym")
+        );
+
+        let response = reqwest::get(format!("{proxied_url}xul.pdb/ID/xul.sym"))
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.bytes().await.unwrap().as_ref(), b"MODULE\n");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let request = &requests[1];
+        assert_eq!(request[0], "GET /symbols/xul.pdb/ID/xul.sym HTTP/1.1");
+        let has_header = |expected: &str| {
+            request
+                .iter()
+                .any(|line| line.eq_ignore_ascii_case(expected))
+        };
+        assert!(
+            has_header("authorization: Bearer secret-token"),
+            "{request:?}"
+        );
+        assert!(has_header("x-client: reliost"), "{request:?}");
+    }
+
+    #[tokio::test]
+    async fn test_proxy_only_forwards_symbol_file_paths_with_the_secret() {
+        let (server_url, requests) = spawn_server(|_| {
+            b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\nMODULE\n"
+        });
+        let settings = settings_with_servers(&format!(
+            r#"[{{ url = "{server_url}", headers = {{ X-Client = "reliost" }} }}]"#
+        ));
+        let (proxy, server) = UpstreamProxy::start(&settings, None).unwrap();
+        tokio::spawn(server.unwrap());
+        let proxied_url = proxy.url_for(&breakpad_servers(&settings)[0]);
+        // "http://<address>/<secret>/0/"
+        let origin = proxied_url
+            .trim_end_matches('/')
+            .rsplitn(3, '/')
+            .last()
+            .unwrap();
+
+        for url in [
+            format!("{origin}/0/xul.pdb/ID/xul.sym"),
+            format!("{origin}/wrong-secret/0/xul.pdb/ID/xul.sym"),
+            format!("{proxied_url}xul.pdb/ID/xul.sym/more"),
+            format!("{proxied_url}%2e%2e/ID/xul.sym"),
+            format!("{proxied_url}xul.pdb/ID/xul.sym?x=1"),
+        ] {
+            let response = reqwest::get(&url).await.unwrap();
+            assert!(response.status().is_client_error(), "{url}");
+        }
+        assert!(requests.lock().unwrap().is_empty());
+
+        let response = reqwest::get(format!("{proxied_url}xul.pdb/ID/xul.sym"))
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_proxy_reports_invalid_server_settings() {
+        let settings = settings_with_servers(
+            r#"[{ url = "https://symbols.example.com/", headers = { "Bad Header" = "x" } }]"#,
+        );
+        let error = UpstreamProxy::start(&settings, None).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+        let settings = settings_with_servers(
+            r#"[{ url = "https://symbols.example.com/", bearer_token = { env = "RELIOST_TEST_UNSET_TOKEN" } }]"#,
+        );
+        let error = UpstreamProxy::start(&settings, None).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    fn settings_with_servers(servers: &str) -> Settings {
+        config::Config::builder()
+            .add_source(config::File::from_str(
+                &format!(
+                    r#"
+                    [server]
+                    host = "127.0.0.1"
+                    port = 0
+
+                    [symbols.breakpad]
+                    servers = {servers}
+                    cache_dir = "cache"
+                    "#
+                ),
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    fn breakpad_servers(settings: &Settings) -> &[SymbolServerSettings] {
+        &settings
+            .symbols
+            .as_ref()
+            .unwrap()
+            .breakpad
+            .as_ref()
+            .unwrap()
+            .servers
+    }
+
+    #[tokio::test]
+    async fn test_proxy_remembers_missing_files() {
+        let (server_url, requests) = spawn_server(|_| {
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        });
+        let settings: Settings = config::Config::builder()
+            .add_source(config::File::from_str(
+                &format!(
+                    r#"
+                    [server]
+                    host = "127.0.0.1"
+                    port = 0
+
+                    [symbols.breakpad]
+                    servers = ["{server_url}"]
+                    cache_dir = "cache"
+                    "#
+                ),
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        let server_settings = &settings
+            .symbols
+            .as_ref()
+            .unwrap()
+            .breakpad
+            .as_ref()
+            .unwrap()
+            .servers[0];
+        let negative_cache = Arc::new(
+            NegativeCache::open(Path::new(":memory:"), Duration::from_secs(3600)).unwrap(),
+        );
+
+        let (proxy, server) =
+            UpstreamProxy::start(&settings, Some(Arc::clone(&negative_cache))).unwrap();
+        tokio::spawn(server.unwrap());
+        let proxied_url = proxy.url_for(server_settings);
+        assert_ne!(proxied_url, server_url);
+
+        for _ in 0..2 {
+            let response = reqwest::get(format!("{proxied_url}ntdll.pdb/ID/ntdll.sym"))
+                .await
+                .unwrap();
+            assert_eq!(response.status().as_u16(), 404);
+        }
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        assert_eq!(negative_cache.remove("ntdll.pdb", "ID").unwrap(), 1);
+        let response = reqwest::get(format!("{proxied_url}ntdll.pdb/ID/ntdll.sym"))
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 404);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+}
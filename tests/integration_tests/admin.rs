@@ -0,0 +1,636 @@
+use reliost::configuration::{
+    CacheQuotaSettings, MinFreeSpace, NegativeCacheSettings, QuotaSettings, SymbolServerSettings,
+};
+
+use crate::helpers::{
+    breakpad_symbol_dir, spawn_app, spawn_app_with_quota, spawn_app_with_settings, StubResponse,
+    StubServer, ADMIN_TOKEN, UPLOAD_TOKEN,
+};
+
+async fn delete(address: &str, path: &str, token: Option<&str>) -> (u16, serde_json::Value) {
+    let client = reqwest::Client::new();
+    let mut request = client.delete(format!("http://{address}{path}"));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.expect("Failed to execute request.");
+    let status = response.status().as_u16();
+    let body = response
+        .bytes()
+        .await
+        .expect("Failed to read response body.");
+    let body = serde_json::from_slice(&body).expect("Expected a JSON body.");
+    (status, body)
+}
+
+fn spawn_app_with_negative_cache(
+    name: &str,
+    servers: Vec<SymbolServerSettings>,
+) -> (String, tokio::task::JoinHandle<Result<(), std::io::Error>>) {
+    let db_path = std::env::temp_dir().join(format!("reliost-test-{name}.db"));
+    let _ = std::fs::remove_file(&db_path);
+    spawn_app_with_settings(|settings| {
+        let symbols = settings.symbols.as_mut().unwrap();
+        symbols.breakpad.as_mut().unwrap().servers = servers;
+        symbols.negative_cache = Some(NegativeCacheSettings {
+            db_path,
+            ttl: std::time::Duration::from_secs(3600),
+        });
+    })
+}
+
+#[tokio::test]
+async fn admin_requires_a_valid_token() {
+    let (address, _join_handle) =
+        spawn_app_with_negative_cache("admin_requires_a_valid_token", Vec::new());
+
+    let (status, body) = delete(&address, "/admin/negative-cache", None).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "unauthorized");
+
+    let (status, body) = delete(&address, "/admin/negative-cache", Some("wrong")).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"]["code"], "forbidden");
+}
+
+#[tokio::test]
+async fn admin_clears_negative_cache_entries() {
+    let symbol_server = StubServer::spawn(|_| StubResponse::NotFound);
+    let (address, _join_handle) = spawn_app_with_negative_cache(
+        "admin_clears_negative_cache_entries",
+        vec![symbol_server.symbol_server()],
+    );
+    let symbolicate = || async {
+        let body = serde_json::json!({
+            "memoryMap": [["negativecachetest.pdb", "0C9B4E5D6D3A4B8B9E4F2A1C3D5E7F901"]],
+            "stacks": [[[0, 4096]]],
+        });
+        let response = reqwest::Client::new()
+            .post(format!("http://{address}/symbolicate/v5"))
+            .body(body.to_string())
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(response.status().as_u16(), 200);
+    };
+    let symbol_file_path =
+        "/negativecachetest.pdb/0C9B4E5D6D3A4B8B9E4F2A1C3D5E7F901/negativecachetest.sym";
+    let upstream_requests = || {
+        symbol_server
+            .requested_paths()
+            .iter()
+            .filter(|path| *path == symbol_file_path)
+            .count()
+    };
+
+    symbolicate().await;
+    assert_eq!(upstream_requests(), 1);
+    // The server didn't have the file, so it isn't asked again.
+    symbolicate().await;
+    assert_eq!(upstream_requests(), 1);
+
+    // Debug IDs are case-insensitive.
+    let (status, body) = delete(
+        &address,
+        "/admin/negative-cache/negativecachetest.pdb/0c9b4e5d6d3a4b8b9e4f2a1c3d5e7f901",
+        Some(ADMIN_TOKEN),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["removed"], 1);
+
+    symbolicate().await;
+    assert_eq!(upstream_requests(), 2);
+}
+
+#[tokio::test]
+async fn admin_negative_cache_not_found_when_disabled() {
+    let (address, _join_handle) = spawn_app();
+
+    let (status, body) = delete(&address, "/admin/negative-cache", Some(ADMIN_TOKEN)).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["code"], "not_found");
+}
+
+#[tokio::test]
+async fn admin_prefetch_requires_a_token() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{address}/admin/prefetch"))
+        .body(r#"{ "libraries": [] }"#)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn admin_prefetch_reports_status() {
+    const DEBUG_ID: &str = "44E4EC8C2F41492B9369D6B9A059577C2";
+    let _ = std::fs::remove_dir_all(breakpad_symbol_dir().join("prefetchfound.pdb"));
+    let symbol_server = StubServer::spawn(|path| {
+        if path.starts_with("/prefetchfound.pdb/") {
+            StubResponse::File(
+                format!(
+                    "MODULE windows x86_64 {DEBUG_ID} prefetchfound.pdb\nFUNC 1000 10 0 main\n"
+                )
+                .into_bytes(),
+            )
+        } else if path.starts_with("/prefetchstalled.pdb/") {
+            StubResponse::Stall
+        } else {
+            StubResponse::NotFound
+        }
+    });
+    let (address, _join_handle) = spawn_app_with_settings(|settings| {
+        let symbols = settings.symbols.as_mut().unwrap();
+        symbols.breakpad.as_mut().unwrap().servers = vec![symbol_server.symbol_server()];
+    });
+
+    let client = reqwest::Client::new();
+    let start_prefetch = |libraries: &'static [&'static str]| {
+        let client = client.clone();
+        let address = address.clone();
+        async move {
+            let libraries: Vec<_> = libraries.iter().map(|name| [*name, DEBUG_ID]).collect();
+            let response = client
+                .post(format!("http://{address}/admin/prefetch"))
+                .bearer_auth(ADMIN_TOKEN)
+                .body(serde_json::json!({ "libraries": libraries }).to_string())
+                .send()
+                .await
+                .expect("Failed to execute request.");
+            assert_eq!(response.status().as_u16(), 202);
+            let location = response.headers()["Location"].to_str().unwrap().to_owned();
+            let body: serde_json::Value = serde_json::from_slice(&response.bytes().await.unwrap())
+                .expect("Expected a JSON body.");
+            assert_eq!(location, format!("/admin/prefetch/{}", body["id"]));
+            location
+        }
+    };
+    let get_status = |location: String| {
+        let client = client.clone();
+        let address = address.clone();
+        async move {
+            let response = client
+                .get(format!("http://{address}{location}"))
+                .bearer_auth(ADMIN_TOKEN)
+                .send()
+                .await
+                .expect("Failed to execute request.");
+            let status: serde_json::Value =
+                serde_json::from_slice(&response.bytes().await.unwrap())
+                    .expect("Expected a JSON body.");
+            status
+        }
+    };
+
+    let location = start_prefetch(&[
+        "prefetchfound.pdb",
+        "prefetchfound.pdb",
+        "prefetchmissing.pdb",
+        "prefetchstalled.pdb",
+    ])
+    .await;
+    // Wait for the first two libraries to finish, and for the download of
+    // the third one to make progress.
+    let mut status = serde_json::Value::Null;
+    for _ in 0..100 {
+        status = get_status(location.clone()).await;
+        let stalled_download = &status["libraries"][2]["downloads"][0];
+        if status["done"] == 1
+            && status["failed"] == 1
+            && stalled_download["bytesSoFar"].as_u64().unwrap_or(0) > 0
+        {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(status["libraries"].as_array().unwrap().len(), 3, "{status}");
+    assert_eq!(status["queued"], 0, "{status}");
+    assert_eq!(status["done"], 1, "{status}");
+    assert_eq!(status["failed"], 1, "{status}");
+    assert_eq!(status["loading"], 1, "{status}");
+    let libraries = &status["libraries"];
+    assert_eq!(libraries[0]["debugName"], "prefetchfound.pdb");
+    assert_eq!(libraries[0]["status"], "done");
+    assert_eq!(libraries[1]["debugName"], "prefetchmissing.pdb");
+    assert_eq!(libraries[1]["status"], "failed");
+    assert!(libraries[1]["error"].is_string(), "{status}");
+    assert_eq!(libraries[2]["status"], "loading");
+    let download = &libraries[2]["downloads"][0];
+    assert!(download["url"]
+        .as_str()
+        .unwrap()
+        .contains("/prefetchstalled.pdb/"));
+    assert!(download["bytesSoFar"].as_u64().unwrap() > 0, "{status}");
+
+    // The file is cached now, so prefetching it again doesn't download it.
+    let found_requests = || {
+        symbol_server
+            .requested_paths()
+            .iter()
+            .filter(|path| path.starts_with("/prefetchfound.pdb/"))
+            .count()
+    };
+    assert_eq!(found_requests(), 1);
+    let location = start_prefetch(&["prefetchfound.pdb"]).await;
+    for _ in 0..100 {
+        status = get_status(location.clone()).await;
+        if status["done"] == 1 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(status["done"], 1, "{status}");
+    assert_eq!(found_requests(), 1);
+
+    let response = client
+        .get(format!("http://{address}/admin/prefetch/12345"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn admin_lists_downloads() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{address}/admin/downloads"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = client
+        .get(format!("http://{address}/admin/downloads"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value =
+        serde_json::from_slice(&response.bytes().await.unwrap()).expect("Expected a JSON body.");
+    assert!(body["inFlight"].is_array());
+    assert!(body["recent"].is_array());
+}
+
+#[tokio::test]
+async fn admin_reports_cache_stats() {
+    let cache_dir = std::env::temp_dir().join("reliost-test-admin-cache-stats");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, |quota| {
+        quota.size_limit = Some(1_000_000_000);
+    });
+    upload_sym(&address, "admin_reports_cache_stats.pdb").await;
+
+    let (status, body) = get_json(&address, "/admin/cache").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["fileCount"], 1);
+    assert_eq!(body["quotas"][0]["sizeLimit"], 1_000_000_000);
+    let upload = body["subdirectories"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|subdir| subdir["name"] == "upload")
+        .expect("Expected the upload directory in the breakdown.");
+    assert_eq!(upload["fileCount"], 1);
+    assert_eq!(body["largestFiles"][0]["size"], upload["totalSize"]);
+}
+
+#[tokio::test]
+async fn admin_cache_stats_per_cache_quota() {
+    let cache_dir = std::env::temp_dir().join("reliost-test-admin-cache-quotas");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, |quota| {
+        quota.size_limit = Some(1_000_000_000);
+        quota.caches = vec![CacheQuotaSettings {
+            dir: cache_dir.join("symbols").join("uploads"),
+            db_path: cache_dir.join("uploads.db"),
+            size_limit: Some(1_000_000),
+            age_limit: None,
+            min_free_space: None,
+        }];
+    });
+    upload_sym(&address, "admin_cache_stats_per_cache_quota.pdb").await;
+
+    let (status, body) = get_json(&address, "/admin/cache").await;
+    assert_eq!(status, 200);
+    // The uploaded file only counts toward the budget of the upload cache.
+    assert_eq!(body["fileCount"], 1);
+    assert_eq!(body["quotas"][0]["fileCount"], 0);
+    assert_eq!(body["quotas"][1]["fileCount"], 1);
+    assert_eq!(body["quotas"][1]["sizeLimit"], 1_000_000);
+}
+
+#[tokio::test]
+async fn new_cache_quota_takes_over_its_files() {
+    let cache_dir = std::env::temp_dir().join("reliost-test-new-cache-quota");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, |_| {});
+    upload_sym(&address, "new_cache_quota_takes_over_its_files.pdb").await;
+    let (_, stats) = get_json(&address, "/admin/cache").await;
+    assert_eq!(stats["quotas"][0]["fileCount"], 1);
+
+    // Restart with a quota of its own for the upload directory.
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, |quota| {
+        quota.caches = vec![CacheQuotaSettings {
+            dir: cache_dir.join("symbols").join("uploads"),
+            db_path: cache_dir.join("uploads.db"),
+            size_limit: None,
+            age_limit: None,
+            min_free_space: None,
+        }];
+    });
+    let stats = wait_for_quota_file_count(&address, 1, 1).await;
+    assert_eq!(stats["fileCount"], 1);
+    assert_eq!(stats["quotas"][0]["fileCount"], 0);
+}
+
+#[tokio::test]
+async fn corrupt_quota_database_is_rebuilt() {
+    let cache_dir = std::env::temp_dir().join("reliost-test-corrupt-quota-db");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let sym_dir = cache_dir
+        .join("symbols")
+        .join("breakpad")
+        .join("corrupt_quota_database_is_rebuilt.pdb")
+        .join("44E4EC8C2F41492B9369D6B9A059577C2");
+    std::fs::create_dir_all(&sym_dir).unwrap();
+    std::fs::write(
+        sym_dir.join("corrupt_quota_database_is_rebuilt.sym"),
+        "MODULE windows x86_64 44E4EC8C2F41492B9369D6B9A059577C2 corrupt_quota_database_is_rebuilt.pdb\n",
+    )
+    .unwrap();
+    std::fs::write(
+        cache_dir.join("symbols.db"),
+        "This is not an SQLite database, but it's long enough to look like one could be.",
+    )
+    .unwrap();
+
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, |_| {});
+
+    // The existing file is added to the fresh inventory.
+    wait_for_quota_file_count(&address, 0, 1).await;
+    let moved_aside = std::fs::read_dir(&cache_dir)
+        .unwrap()
+        .flatten()
+        .any(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("symbols.db.corrupt-")
+        });
+    assert!(moved_aside, "Expected the corrupt database to be kept.");
+}
+
+#[tokio::test]
+async fn low_free_space_evicts_files() {
+    let cache_dir = std::env::temp_dir().join("reliost-test-low-free-space");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    // No file system has all of its space free, so any file is too much.
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, |quota| {
+        quota.min_free_space = Some(MinFreeSpace::Percent(100.0));
+    });
+    upload_sym(&address, "low_free_space_evicts_files.pdb").await;
+
+    let sym_file = cache_dir
+        .join("symbols")
+        .join("uploads")
+        .join("low_free_space_evicts_files.pdb")
+        .join("44E4EC8C2F41492B9369D6B9A059577C2")
+        .join("low_free_space_evicts_files.sym");
+    // The file may be evicted as soon as it's written, but not its directory.
+    assert!(sym_file.parent().unwrap().is_dir());
+    let mut file_count = 1;
+    for _ in 0..50 {
+        let (_, stats) = get_json(&address, "/admin/cache").await;
+        file_count = stats["fileCount"].as_u64().unwrap();
+        if file_count == 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(file_count, 0, "Expected the uploaded file to be evicted.");
+    assert!(!sym_file.exists());
+}
+
+#[tokio::test]
+async fn quota_database_directory_is_created() {
+    let cache_dir = std::env::temp_dir().join("reliost-test-quota-db-dir");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, |quota| {
+        quota.db_path = cache_dir.join("db").join("symbols.db");
+    });
+
+    let (status, _) = get_json(&address, "/admin/cache").await;
+    assert_eq!(status, 200);
+    assert!(cache_dir.join("db").join("symbols.db").exists());
+}
+
+/// Waits until the inventory of the quota manager at `index` in
+/// `/admin/cache` has `file_count` files, as it's rebuilt in the background.
+async fn wait_for_quota_file_count(
+    address: &str,
+    index: usize,
+    file_count: u64,
+) -> serde_json::Value {
+    let mut stats = serde_json::Value::Null;
+    for _ in 0..50 {
+        let status;
+        (status, stats) = get_json(address, "/admin/cache").await;
+        assert_eq!(status, 200);
+        if stats["quotas"][index]["fileCount"] == file_count {
+            return stats;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("Expected {file_count} files in quota {index}, got {stats}");
+}
+
+#[tokio::test]
+async fn admin_cache_stats_not_found_without_quota() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{address}/admin/cache"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+async fn get_json(address: &str, path: &str) -> (u16, serde_json::Value) {
+    let response = reqwest::Client::new()
+        .get(format!("http://{address}{path}"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let status = response.status().as_u16();
+    let body =
+        serde_json::from_slice(&response.bytes().await.unwrap()).expect("Expected a JSON body.");
+    (status, body)
+}
+
+async fn upload_sym(address: &str, debug_name: &str) {
+    let response = reqwest::Client::new()
+        .post(format!("http://{address}/upload"))
+        .header("Auth-Token", UPLOAD_TOKEN)
+        .body(format!(
+            "MODULE windows x86_64 44E4EC8C2F41492B9369D6B9A059577C2 {debug_name}\nFUNC 1000 10 0 main\n"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+#[tokio::test]
+async fn admin_pins_are_stored() {
+    let cache_dir = std::env::temp_dir().join("reliost-test-admin-pins");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let configure = |quota: &mut QuotaSettings| {
+        quota.pinned = vec!["admin_pins_configured*.pdb".into()];
+    };
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, configure);
+    upload_sym(&address, "admin_pins_configured.pdb").await;
+    upload_sym(&address, "admin_pins_runtime.pdb").await;
+
+    // The files of pinned libraries are counted once, by their quota
+    // manager.
+    let (_, stats) = get_json(&address, "/admin/cache").await;
+    assert_eq!(stats["fileCount"], 2);
+    assert_eq!(stats["quotas"][0]["fileCount"], 2);
+    assert_eq!(stats["pinned"]["fileCount"], 1);
+
+    let response = reqwest::Client::new()
+        .put(format!(
+            "http://{address}/admin/pins/admin_pins_runtime.pdb"
+        ))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value =
+        serde_json::from_slice(&response.bytes().await.unwrap()).expect("Expected a JSON body.");
+    assert_eq!(body["files"], 1);
+    let (_, stats) = get_json(&address, "/admin/cache").await;
+    assert_eq!(stats["fileCount"], 2);
+    assert_eq!(stats["pinned"]["fileCount"], 2);
+
+    // The added pin survives a restart.
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, configure);
+    let (status, pins) = get_json(&address, "/admin/pins").await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        pins["pins"],
+        serde_json::json!([
+            { "pin": "admin_pins_configured*.pdb", "configured": true },
+            { "pin": "admin_pins_runtime.pdb", "configured": false },
+        ])
+    );
+
+    let (status, body) = delete(
+        &address,
+        "/admin/pins/admin_pins_configured*.pdb",
+        Some(ADMIN_TOKEN),
+    )
+    .await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"]["code"], "conflict");
+
+    let (status, body) = delete(
+        &address,
+        "/admin/pins/admin_pins_runtime.pdb",
+        Some(ADMIN_TOKEN),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["files"], 1);
+    let (_, stats) = get_json(&address, "/admin/cache").await;
+    assert_eq!(stats["fileCount"], 2);
+    assert_eq!(stats["pinned"]["fileCount"], 1);
+
+    let (status, _) = delete(
+        &address,
+        "/admin/pins/admin_pins_runtime.pdb",
+        Some(ADMIN_TOKEN),
+    )
+    .await;
+    assert_eq!(status, 404);
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, configure);
+    let (_, pins) = get_json(&address, "/admin/pins").await;
+    assert_eq!(pins["pins"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn admin_pins_not_found_without_pins_db() {
+    let cache_dir = std::env::temp_dir().join("reliost-test-admin-pins-no-db");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, |quota| {
+        quota.pins_db_path = None;
+    });
+
+    let response = reqwest::Client::new()
+        .put(format!("http://{address}/admin/pins/xul.pdb"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn pinned_files_are_evicted_last() {
+    let cache_dir = std::env::temp_dir().join("reliost-test-pinned-evicted-last");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let sym_file = |debug_name: &str| {
+        let stem = debug_name.strip_suffix(".pdb").unwrap();
+        cache_dir
+            .join("symbols")
+            .join("uploads")
+            .join(debug_name)
+            .join("44E4EC8C2F41492B9369D6B9A059577C2")
+            .join(format!("{stem}.sym"))
+    };
+    // Room for two of the three files, which are the same size.
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, |quota| {
+        quota.pinned = vec!["evicted_last_a.pdb".into()];
+        quota.size_limit = Some(2 * 100);
+    });
+    upload_sym(&address, "evicted_last_a.pdb").await;
+    let file_size = std::fs::metadata(sym_file("evicted_last_a.pdb"))
+        .unwrap()
+        .len();
+    assert!(2 * file_size <= 200 && 3 * file_size > 200);
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    upload_sym(&address, "evicted_last_b.pdb").await;
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    upload_sym(&address, "evicted_last_c.pdb").await;
+
+    // The pinned file is the least recently used one, but the next one is
+    // evicted instead.
+    wait_for_quota_file_count(&address, 0, 2).await;
+    assert!(sym_file("evicted_last_a.pdb").exists());
+    assert!(!sym_file("evicted_last_b.pdb").exists());
+    assert!(sym_file("evicted_last_c.pdb").exists());
+}
+
+#[tokio::test]
+async fn admin_pins_not_found_without_quota() {
+    let (address, _join_handle) = spawn_app();
+
+    let (status, body) = get_json(&address, "/admin/pins").await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["code"], "not_found");
+}
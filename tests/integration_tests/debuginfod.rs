@@ -0,0 +1,47 @@
+use reliost::configuration::DebuginfodSymbolSettings;
+
+use crate::helpers::{spawn_app_with_settings, StubResponse, StubServer};
+
+#[tokio::test]
+async fn symbolicate_v5_queries_debuginfod_by_code_id() {
+    let debuginfod_server = StubServer::spawn(|_| StubResponse::NotFound);
+    let (address, _join_handle) = spawn_app_with_settings(|settings| {
+        let symbols = settings.symbols.as_mut().unwrap();
+        symbols.debuginfod = Some(DebuginfodSymbolSettings {
+            servers: vec![debuginfod_server.url.clone()],
+            cache_dir: std::env::temp_dir().join("reliost-test-debuginfod"),
+        });
+    });
+
+    let code_id = "a7b2b1f1b0e41bb51da4b4e5a2c0d51d12345678";
+    let body = serde_json::json!({
+        "memoryMap": [["libdebuginfodtest.so", "F1B1B2A7E4B0B51B1DA4B4E5A2C0D51D0", code_id]],
+        "stacks": [[[0, 4096]]],
+    });
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{address}/symbolicate/v5"))
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response
+        .bytes()
+        .await
+        .expect("Failed to read response body.");
+    let body: serde_json::Value = serde_json::from_slice(&body).expect("Expected a JSON body.");
+    assert_eq!(
+        body["results"][0]["found_modules"]
+            ["libdebuginfodtest.so/F1B1B2A7E4B0B51B1DA4B4E5A2C0D51D0"],
+        false
+    );
+
+    let requested_paths = debuginfod_server.requested_paths();
+    assert!(
+        requested_paths
+            .iter()
+            .any(|path| path.starts_with(&format!("/buildid/{code_id}/"))),
+        "Expected a debuginfod request for {code_id}, got {requested_paths:?}"
+    );
+}
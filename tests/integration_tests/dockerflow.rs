@@ -1,20 +1,92 @@
-use std::net::TcpListener;
+use reliost::configuration::MinFreeSpace;
 
-use reliost::{configuration::ServerSettings, configuration::Settings};
-use tokio::task::JoinHandle;
-
-#[tokio::test]
-async fn heartbeat_works() {
-    let (address, _join_handle) = spawn_app();
+use crate::helpers::{spawn_app, spawn_app_with_quota, spawn_app_with_settings};
 
+async fn get_heartbeat(address: &str) -> (u16, serde_json::Value) {
     let client = reqwest::Client::new();
     let response = client
         .get(format!("http://{address}/__heartbeat__"))
         .send()
         .await
         .expect("Failed to execute request.");
+    let status = response.status().as_u16();
+    let body = response
+        .bytes()
+        .await
+        .expect("Failed to read response body.");
+    let body = serde_json::from_slice(&body).expect("Expected a JSON body.");
+    (status, body)
+}
+
+#[tokio::test]
+async fn heartbeat_works() {
+    let (address, _join_handle) = spawn_app();
+
+    let (status, body) = get_heartbeat(&address).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ok");
+    let checks = body["checks"].as_array().unwrap();
+    assert!(checks
+        .iter()
+        .any(|check| check["name"] == "breakpad.cache_dir" && check["status"] == "ok"));
+}
+
+#[tokio::test]
+async fn concurrent_heartbeats_dont_interfere() {
+    let (address, _join_handle) = spawn_app();
+
+    let heartbeats: Vec<_> = (0..8)
+        .map(|_| {
+            let address = address.clone();
+            tokio::spawn(async move { get_heartbeat(&address).await })
+        })
+        .collect();
+    for heartbeat in heartbeats {
+        let (status, body) = heartbeat.await.unwrap();
+        assert_eq!(status, 200, "{body}");
+    }
+}
+
+#[tokio::test]
+async fn heartbeat_fails_without_enough_free_space() {
+    let (address, _join_handle) = spawn_app_with_settings(|settings| {
+        settings.heartbeat.min_free_space = Some(MinFreeSpace::Bytes(u64::MAX));
+    });
+
+    let (status, body) = get_heartbeat(&address).await;
+    assert_eq!(status, 500);
+    assert_eq!(body["status"], "error");
+    let checks = body["checks"].as_array().unwrap();
+    assert!(checks.iter().any(
+        |check| check["name"] == "breakpad.cache_dir.free_space" && check["status"] == "error"
+    ));
+}
+
+#[tokio::test]
+async fn heartbeat_fails_without_a_quota_manager() {
+    let cache_dir = std::env::temp_dir().join("reliost-test-unwritable-quota-db");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    std::fs::create_dir_all(&cache_dir).unwrap();
+    // A file where the database's directory should be.
+    std::fs::write(cache_dir.join("not-a-dir"), "").unwrap();
+    let (address, _join_handle) = spawn_app_with_quota(&cache_dir, |quota| {
+        quota.db_path = cache_dir.join("not-a-dir").join("symbols.db");
+    });
+
+    // The server runs without the quota manager, and says so.
+    let (status, body) = get_heartbeat(&address).await;
+    assert_eq!(status, 500);
+    let checks = body["checks"].as_array().unwrap();
+    assert!(
+        checks
+            .iter()
+            .any(|check| check["name"] == "quota_manager" && check["status"] == "error"),
+        "{body}"
+    );
+    let response = reqwest::get(format!("http://{address}/__lbheartbeat__"))
+        .await
+        .expect("Failed to execute request.");
     assert!(response.status().is_success());
-    assert_eq!(response.content_length(), Some(0));
 }
 
 #[tokio::test]
@@ -30,21 +102,3 @@ async fn lbheartbeat_works() {
     assert!(response.status().is_success());
     assert_eq!(response.content_length(), Some(0));
 }
-
-fn spawn_app() -> (String, JoinHandle<Result<(), std::io::Error>>) {
-    let host = "127.0.0.1";
-    let listener = TcpListener::bind(format!("{host}:0")).expect("Failed to bind random port");
-    let port = listener.local_addr().unwrap().port();
-    let settings = Settings {
-        server: ServerSettings {
-            host: host.to_string(),
-            port,
-        },
-        symbols: None,
-        quota: None,
-        self_profiles: None,
-    };
-    let (server, _) = reliost::startup::run(listener, settings).expect("Failed to bind address.");
-    let join_handle = tokio::spawn(server);
-    (format!("{host}:{port}"), join_handle)
-}
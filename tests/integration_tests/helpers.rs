@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use reliost::configuration::{
+    AdminSettings, BreakpadSymbolSettings, QuotaSettings, Secret, ServerSettings, Settings,
+    SourceSettings, SymbolServerSettings, SymbolSettings, UploadSettings,
+};
+use tokio::task::JoinHandle;
+
+/// The request body limit of the test app, small enough to be exceeded by
+/// tests.
+pub const MAX_REQUEST_BODY_SIZE: u64 = 1000 * 1000;
+
+/// The directory which the test app uses as its local breakpad symbol
+/// directory. Tests which put files in here should use unique file names.
+pub fn breakpad_symbol_dir() -> PathBuf {
+    std::env::temp_dir().join("reliost-test-breakpad-symbols")
+}
+
+/// The token which the test app accepts for uploads.
+pub const UPLOAD_TOKEN: &str = "test-upload-token";
+
+/// The token which the test app accepts for the `/admin/` endpoints.
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
+/// The files with [`UPLOAD_TOKEN`] and [`ADMIN_TOKEN`], which the test app
+/// reads its tokens from. The tests run in parallel, so they can't safely
+/// pass the tokens in environment variables.
+fn token_files() -> &'static (PathBuf, PathBuf) {
+    static TOKEN_FILES: OnceLock<(PathBuf, PathBuf)> = OnceLock::new();
+    TOKEN_FILES.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!("reliost-test-tokens-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let upload_token_file = dir.join("upload");
+        std::fs::write(&upload_token_file, UPLOAD_TOKEN).unwrap();
+        let admin_token_file = dir.join("admin");
+        std::fs::write(&admin_token_file, ADMIN_TOKEN).unwrap();
+        (upload_token_file, admin_token_file)
+    })
+}
+
+pub fn upload_dir() -> PathBuf {
+    std::env::temp_dir().join("reliost-test-uploads")
+}
+
+pub fn spawn_app() -> (String, JoinHandle<Result<(), std::io::Error>>) {
+    spawn_app_with_settings(|_| {})
+}
+
+/// Like [`spawn_app`], but lets the test adjust the settings first.
+pub fn spawn_app_with_settings(
+    configure: impl FnOnce(&mut Settings),
+) -> (String, JoinHandle<Result<(), std::io::Error>>) {
+    let host = "127.0.0.1";
+    let listener = TcpListener::bind(format!("{host}:0")).expect("Failed to bind random port");
+    let port = listener.local_addr().unwrap().port();
+    let mut settings = Settings {
+        server: ServerSettings {
+            host: host.to_string(),
+            port,
+            max_request_body_size: Some(MAX_REQUEST_BODY_SIZE),
+        },
+        symbols: Some(SymbolSettings {
+            breakpad: Some(BreakpadSymbolSettings {
+                dirs: Vec::new(),
+                servers: Vec::new(),
+                cache_dir: breakpad_symbol_dir(),
+                symindex_dir: None,
+            }),
+            windows: None,
+            debuginfod: None,
+            negative_cache: None,
+        }),
+        quota: None,
+        self_profiles: None,
+        compression: Default::default(),
+        symbolication: Default::default(),
+        source: Some(SourceSettings {
+            cache_dir: std::env::temp_dir().join("reliost-test-source-cache"),
+            providers: Vec::new(),
+        }),
+        upload: Some(UploadSettings {
+            dir: upload_dir(),
+            tokens: vec![Secret::File(token_files().0.clone())],
+            max_size: Some(MAX_REQUEST_BODY_SIZE),
+            exempt_from_eviction: false,
+        }),
+        admin: AdminSettings {
+            tokens: vec![Secret::File(token_files().1.clone())],
+        },
+        heartbeat: Default::default(),
+    };
+    configure(&mut settings);
+    let (server, _, _) =
+        reliost::startup::run(listener, settings).expect("Failed to bind address.");
+    let join_handle = tokio::spawn(server);
+    (format!("{host}:{port}"), join_handle)
+}
+
+/// Like [`spawn_app`], but with a quota manager for `<dir>/symbols`, which
+/// contains the upload directory, its database at `<dir>/symbols.db`, and
+/// the pins database at `<dir>/pins.db`.
+/// There are no limits unless `configure` sets them.
+pub fn spawn_app_with_quota(
+    dir: &Path,
+    configure: impl FnOnce(&mut QuotaSettings),
+) -> (String, JoinHandle<Result<(), std::io::Error>>) {
+    let mut quota = QuotaSettings {
+        managed_dir: dir.join("symbols"),
+        db_path: dir.join("symbols.db"),
+        size_limit: None,
+        age_limit: None,
+        min_free_space: None,
+        caches: Vec::new(),
+        pinned: Vec::new(),
+        pins_db_path: Some(dir.join("pins.db")),
+    };
+    configure(&mut quota);
+    spawn_app_with_settings(|settings| {
+        settings.quota = Some(quota);
+        settings.upload.as_mut().unwrap().dir = dir.join("symbols").join("uploads");
+    })
+}
+
+/// How a [`StubServer`] responds to a request.
+pub enum StubResponse {
+    NotFound,
+    ServerError,
+    File(Vec<u8>),
+    /// Sends the headers of a large file and then one byte at a time, slowly
+    /// enough that the download never finishes, until the client hangs up.
+    Stall,
+}
+
+/// A stand-in symbol server which records the paths of the requests it
+/// receives.
+pub struct StubServer {
+    pub url: String,
+    requested_paths: Arc<Mutex<Vec<String>>>,
+    abandoned_paths: Arc<Mutex<Vec<String>>>,
+}
+
+impl StubServer {
+    pub fn spawn(respond: impl Fn(&str) -> StubResponse + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requested_paths = Arc::new(Mutex::new(Vec::new()));
+        let abandoned_paths = Arc::new(Mutex::new(Vec::new()));
+        let server = Self {
+            url,
+            requested_paths: Arc::clone(&requested_paths),
+            abandoned_paths: Arc::clone(&abandoned_paths),
+        };
+        let respond = Arc::new(respond);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                let respond = Arc::clone(&respond);
+                let requested_paths = Arc::clone(&requested_paths);
+                let abandoned_paths = Arc::clone(&abandoned_paths);
+                std::thread::spawn(move || {
+                    let Some(path) = read_request_path(&stream) else {
+                        return;
+                    };
+                    requested_paths.lock().unwrap().push(path.clone());
+                    if !write_response(stream, respond(&path)) {
+                        abandoned_paths.lock().unwrap().push(path);
+                    }
+                });
+            }
+        });
+        server
+    }
+
+    pub fn requested_paths(&self) -> Vec<String> {
+        self.requested_paths.lock().unwrap().clone()
+    }
+
+    /// The paths of the requests which the client hung up on before the
+    /// response was complete.
+    pub fn abandoned_paths(&self) -> Vec<String> {
+        self.abandoned_paths.lock().unwrap().clone()
+    }
+
+    /// Settings for using this server as a symbol server.
+    pub fn symbol_server(&self) -> SymbolServerSettings {
+        SymbolServerSettings {
+            url: self.url.clone(),
+            headers: BTreeMap::new(),
+            bearer_token: None,
+            basic_auth: None,
+            timeout: None,
+            retries: 0,
+        }
+    }
+}
+
+/// Reads the request line and the headers, and returns the request path.
+fn read_request_path(stream: &TcpStream) -> Option<String> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 || header == "\r\n" {
+            break;
+        }
+    }
+    Some(request_line.split(' ').nth(1)?.to_owned())
+}
+
+/// Returns false if the client hung up before the response was complete.
+fn write_response(mut stream: TcpStream, response: StubResponse) -> bool {
+    match response {
+        StubResponse::NotFound => stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .is_ok(),
+        StubResponse::ServerError => stream
+            .write_all(
+                b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            )
+            .is_ok(),
+        StubResponse::File(contents) => {
+            let headers = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                contents.len()
+            );
+            stream.write_all(headers.as_bytes()).is_ok() && stream.write_all(&contents).is_ok()
+        }
+        StubResponse::Stall => {
+            let headers =
+                "HTTP/1.1 200 OK\r\nContent-Length: 1000000000\r\nConnection: close\r\n\r\n";
+            if stream.write_all(headers.as_bytes()).is_err() {
+                return false;
+            }
+            // Writes only fail once the client has closed the connection.
+            for _ in 0..600 {
+                std::thread::sleep(Duration::from_millis(50));
+                if stream.write_all(b"x").and_then(|_| stream.flush()).is_err() {
+                    return false;
+                }
+            }
+            true
+        }
+    }
+}
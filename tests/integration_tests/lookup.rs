@@ -0,0 +1,106 @@
+use crate::helpers::{
+    breakpad_symbol_dir, spawn_app, spawn_app_with_settings, StubResponse, StubServer,
+};
+
+#[tokio::test]
+async fn lookup_v1_rejects_invalid_address() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!(
+            "http://{address}/lookup/v1/xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2/0xnothex"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn lookup_v1_rejects_invalid_debug_id() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{address}/lookup/v1/xul.pdb/nope/0x1000"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn lookup_v1_distinguishes_missing_and_broken_symbols() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!(
+            "http://{address}/lookup/v1/lookup_missing.pdb/44E4EC8C2F41492B9369D6B9A059577C2/0x1000"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+
+    let dir = breakpad_symbol_dir().join("lookup_broken.pdb/44E4EC8C2F41492B9369D6B9A059577C2");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("lookup_broken.sym"), "not a symbol file").unwrap();
+    let response = client
+        .get(format!(
+            "http://{address}/lookup/v1/lookup_broken.pdb/44E4EC8C2F41492B9369D6B9A059577C2/0x1000"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 500);
+    assert!(response.headers().get("Cache-Control").is_none());
+}
+
+#[tokio::test]
+async fn lookup_v1_reports_failed_downloads_as_bad_gateway() {
+    let server = StubServer::spawn(|path| {
+        if path.contains("lookup_missing_upstream") {
+            StubResponse::NotFound
+        } else {
+            StubResponse::ServerError
+        }
+    });
+    let cache_dir = std::env::temp_dir().join("reliost-test-lookup-server-error");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let (address, _join_handle) = spawn_app_with_settings(|settings| {
+        let breakpad = settings
+            .symbols
+            .as_mut()
+            .unwrap()
+            .breakpad
+            .as_mut()
+            .unwrap();
+        breakpad.servers = vec![server.symbol_server()];
+        breakpad.cache_dir = cache_dir;
+    });
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!(
+            "http://{address}/lookup/v1/lookup_server_error.pdb/44E4EC8C2F41492B9369D6B9A059577C2/0x1000"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 502);
+    let body: serde_json::Value =
+        serde_json::from_slice(&response.bytes().await.unwrap()).expect("Expected a JSON body.");
+    assert_eq!(body["error"]["code"], "bad_gateway");
+
+    // The server answering 404 is still a missing library.
+    let response = client
+        .get(format!(
+            "http://{address}/lookup/v1/lookup_missing_upstream.pdb/44E4EC8C2F41492B9369D6B9A059577C2/0x1000"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+}
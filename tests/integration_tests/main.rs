@@ -1 +1,13 @@
+mod admin;
+// wholesym only uses debuginfod on Linux.
+#[cfg(target_os = "linux")]
+mod debuginfod;
 mod dockerflow;
+mod helpers;
+mod lookup;
+mod metrics;
+mod source;
+mod symbol_dirs;
+mod symbol_files;
+mod symbolicate;
+mod upload;
@@ -0,0 +1,38 @@
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn metrics_records_requests_by_route() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{address}/__lbheartbeat__"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+
+    // Requests are recorded once their response body has been sent, which
+    // can be after the client has received it.
+    let expected = r#"reliost_http_request_duration_seconds_count{method="GET",route="/__lbheartbeat__",status="200"} 1"#;
+    let mut body = String::new();
+    for _ in 0..50 {
+        let response = client
+            .get(format!("http://{address}/metrics"))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain"));
+        body = response.text().await.unwrap();
+        if body.contains(expected) {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(body.contains(expected), "{body}");
+    assert!(body.contains("reliost_file_hits_total 0"), "{body}");
+}
@@ -0,0 +1,125 @@
+use reliost::configuration::{SourceMappingKind, SourceProviderSettings};
+
+use crate::helpers::{
+    breakpad_symbol_dir, spawn_app, spawn_app_with_settings, StubResponse, StubServer,
+};
+
+async fn post_source_v1(address: &str, body: serde_json::Value) -> (u16, serde_json::Value) {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{address}/source/v1"))
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let status = response.status().as_u16();
+    let body = response
+        .bytes()
+        .await
+        .expect("Failed to read response body.");
+    let body = serde_json::from_slice(&body).expect("Expected a JSON body.");
+    (status, body)
+}
+
+#[tokio::test]
+async fn source_v1_rejects_path_traversal() {
+    let (address, _join_handle) = spawn_app();
+
+    let (status, body) = post_source_v1(
+        &address,
+        serde_json::json!({
+            "debugName": "reliost",
+            "debugId": "44E4EC8C2F41492B9369D6B9A059577C2",
+            "moduleOffset": "0x1000",
+            "file": "git:github.com/mstange/reliost:../../etc/passwd:0123abcd",
+        }),
+    )
+    .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "invalid_source_path");
+}
+
+#[tokio::test]
+async fn source_v1_rejects_unmapped_paths() {
+    let (address, _join_handle) = spawn_app();
+
+    let (status, body) = post_source_v1(
+        &address,
+        serde_json::json!({
+            "debugName": "reliost",
+            "debugId": "44E4EC8C2F41492B9369D6B9A059577C2",
+            "moduleOffset": "0x1000",
+            "file": "/etc/passwd",
+        }),
+    )
+    .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "invalid_source_path");
+}
+
+#[tokio::test]
+async fn source_v1_fetches_and_caches_referenced_files() {
+    const SOURCE: &str = "fn main() {\n    println!(\"Hello\");\n}\n";
+    let server = StubServer::spawn(|path| {
+        if path == "/mstange/reliost/0123abcd/src/main.rs" {
+            StubResponse::File(SOURCE.as_bytes().to_vec())
+        } else {
+            StubResponse::NotFound
+        }
+    });
+    let cache_dir = std::env::temp_dir().join("reliost-test-source-fetch-cache");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let (address, _join_handle) = spawn_app_with_settings(|settings| {
+        let source = settings.source.as_mut().unwrap();
+        source.cache_dir = cache_dir;
+        source.providers = vec![SourceProviderSettings::Http {
+            mapping: SourceMappingKind::Git,
+            repo_prefix: "github.com/".into(),
+            template: format!("{}{{repo}}/{{rev}}/{{path}}", server.url),
+        }];
+    });
+
+    // The debug info at 0x1010 references the file.
+    let file = "git:github.com/mstange/reliost:src/main.rs:0123abcd";
+    let sym_dir = breakpad_symbol_dir().join("source_fetch.pdb/44E4EC8C2F41492B9369D6B9A059577C2");
+    std::fs::create_dir_all(&sym_dir).unwrap();
+    std::fs::write(
+        sym_dir.join("source_fetch.sym"),
+        format!(
+            "MODULE windows x86_64 44E4EC8C2F41492B9369D6B9A059577C2 source_fetch.pdb\n\
+             FILE 0 {file}\n\
+             FUNC 1000 100 0 main\n\
+             1000 100 2 0\n"
+        ),
+    )
+    .unwrap();
+    let request = serde_json::json!({
+        "debugName": "source_fetch.pdb",
+        "debugId": "44E4EC8C2F41492B9369D6B9A059577C2",
+        "moduleOffset": "0x1010",
+        "file": file,
+    });
+
+    let (status, body) = post_source_v1(&address, request.clone()).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["file"], file);
+    assert_eq!(body["source"], SOURCE);
+
+    // The second request is answered from the cache, compressed.
+    let response = reqwest::Client::new()
+        .post(format!("http://{address}/source/v1"))
+        .header("Accept-Encoding", "zstd")
+        .body(request.to_string())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("Content-Encoding").unwrap(), "zstd");
+    let body = zstd::decode_all(&response.bytes().await.unwrap()[..]).unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).expect("Expected a JSON body.");
+    assert_eq!(body["source"], SOURCE);
+    assert_eq!(
+        server.requested_paths(),
+        ["/mstange/reliost/0123abcd/src/main.rs"]
+    );
+}
@@ -0,0 +1,134 @@
+use std::path::{Path, PathBuf};
+
+use reliost::configuration::WindowsSymbolSettings;
+
+use crate::helpers::{spawn_app_with_settings, StubResponse, StubServer};
+
+const DEBUG_ID: &str = "44E4EC8C2F41492B9369D6B9A059577C2";
+
+/// Creates an empty directory for a test.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("reliost-test-{name}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a symbol file whose only function is `function`, at
+/// `<dir>/<debugName>/<debugId>/<fileName>`.
+fn write_symbol_file(dir: &Path, debug_name: &str, file_name: &str, function: &str) {
+    let dir = dir.join(debug_name).join(DEBUG_ID);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join(file_name),
+        format!("MODULE windows x86_64 {DEBUG_ID} {debug_name}\nFUNC 1000 100 0 {function}\n"),
+    )
+    .unwrap();
+}
+
+/// Looks up 0x1000 and returns the function name, if any.
+async fn look_up_function(address: &str, debug_name: &str) -> Option<String> {
+    let response = reqwest::Client::new()
+        .get(format!(
+            "http://{address}/lookup/v1/{debug_name}/{DEBUG_ID}/0x1000"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    if response.status().as_u16() != 200 {
+        return None;
+    }
+    let body: serde_json::Value =
+        serde_json::from_slice(&response.bytes().await.unwrap()).expect("Expected a JSON body.");
+    Some(body["function"].as_str()?.to_owned())
+}
+
+#[tokio::test]
+async fn breakpad_dirs_are_searched_in_order_before_the_servers() {
+    let server = StubServer::spawn(|_| {
+        StubResponse::File(
+            format!("MODULE windows x86_64 {DEBUG_ID} breakpad_order.pdb\nFUNC 1000 100 0 from_server\n")
+                .into_bytes(),
+        )
+    });
+    let first_dir = test_dir("breakpad-order-first");
+    let second_dir = test_dir("breakpad-order-second");
+    let cache_dir = test_dir("breakpad-order-cache");
+    let (address, _join_handle) = spawn_app_with_settings(|settings| {
+        let breakpad = settings
+            .symbols
+            .as_mut()
+            .unwrap()
+            .breakpad
+            .as_mut()
+            .unwrap();
+        breakpad.dirs = vec![first_dir.clone(), second_dir.clone()];
+        breakpad.servers = vec![server.symbol_server()];
+        breakpad.cache_dir = cache_dir;
+    });
+
+    let debug_name = "breakpad_order.pdb";
+    write_symbol_file(
+        &first_dir,
+        debug_name,
+        "breakpad_order.sym",
+        "from_first_dir",
+    );
+    write_symbol_file(
+        &second_dir,
+        debug_name,
+        "breakpad_order.sym",
+        "from_second_dir",
+    );
+    assert_eq!(
+        look_up_function(&address, debug_name).await.as_deref(),
+        Some("from_first_dir")
+    );
+
+    std::fs::remove_dir_all(first_dir.join(debug_name)).unwrap();
+    assert_eq!(
+        look_up_function(&address, debug_name).await.as_deref(),
+        Some("from_second_dir")
+    );
+    assert!(server.requested_paths().is_empty());
+
+    std::fs::remove_dir_all(second_dir.join(debug_name)).unwrap();
+    assert_eq!(
+        look_up_function(&address, debug_name).await.as_deref(),
+        Some("from_server")
+    );
+    assert_eq!(
+        server.requested_paths(),
+        [format!("/{debug_name}/{DEBUG_ID}/breakpad_order.sym")]
+    );
+}
+
+#[tokio::test]
+async fn windows_dirs_are_searched_in_order_before_the_servers() {
+    let server = StubServer::spawn(|_| StubResponse::NotFound);
+    let first_dir = test_dir("windows-order-first");
+    let second_dir = test_dir("windows-order-second");
+    let cache_dir = test_dir("windows-order-cache");
+    // The environment must not change where we look.
+    std::env::set_var("_NT_SYMBOL_PATH", format!("srv*{}", first_dir.display()));
+    let (address, _join_handle) = spawn_app_with_settings(|settings| {
+        settings.symbols.as_mut().unwrap().windows = Some(WindowsSymbolSettings {
+            dirs: vec![second_dir.clone()],
+            servers: vec![server.symbol_server()],
+            cache_dir,
+        });
+    });
+
+    let debug_name = "windows_order.pdb";
+    write_symbol_file(&first_dir, debug_name, debug_name, "from_environment");
+    write_symbol_file(&second_dir, debug_name, debug_name, "from_local_store");
+    look_up_function(&address, debug_name).await;
+    assert!(server.requested_paths().is_empty());
+
+    std::fs::remove_dir_all(second_dir.join(debug_name)).unwrap();
+    look_up_function(&address, debug_name).await;
+    assert_eq!(
+        server.requested_paths(),
+        [format!("/{debug_name}/{DEBUG_ID}/{debug_name}")]
+    );
+}
@@ -0,0 +1,117 @@
+use crate::helpers::{
+    breakpad_symbol_dir, spawn_app, spawn_app_with_settings, StubResponse, StubServer,
+};
+
+const DEBUG_ID: &str = "44E4EC8C2F41492B9369D6B9A059577C2";
+
+#[tokio::test]
+async fn symbol_file_serves_cached_files() {
+    let (address, _join_handle) = spawn_app();
+
+    let dir = breakpad_symbol_dir()
+        .join("symbol_file_serves_cached_files.pdb")
+        .join(DEBUG_ID);
+    std::fs::create_dir_all(&dir).unwrap();
+    let contents =
+        format!("MODULE windows x86_64 {DEBUG_ID} symbol_file_serves_cached_files.pdb\n");
+    std::fs::write(dir.join("symbol_file_serves_cached_files.sym"), &contents).unwrap();
+
+    let url = format!(
+        "http://{address}/symbol_file_serves_cached_files.pdb/{DEBUG_ID}/symbol_file_serves_cached_files.sym"
+    );
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.bytes().await.unwrap(), contents.as_bytes());
+
+    let response = client
+        .head(&url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    // reqwest's content_length() is the length of the (empty) body here.
+    assert_eq!(
+        response.headers()[reqwest::header::CONTENT_LENGTH],
+        contents.len().to_string().as_str()
+    );
+}
+
+#[tokio::test]
+async fn symbol_file_returns_404_for_missing_files() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!(
+            "http://{address}/missing.pdb/{DEBUG_ID}/missing.sym"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn symbol_file_rejects_path_traversal() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("http://{address}/..%2F..%2Fetc/{DEBUG_ID}/passwd"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn symbol_file_head_only_checks_the_cache() {
+    let contents = format!("MODULE windows x86_64 {DEBUG_ID} symbol_file_fetch.pdb\n");
+    let server_contents = contents.clone();
+    let server = StubServer::spawn(move |_| StubResponse::File(server_contents.clone().into()));
+    let cache_dir = std::env::temp_dir().join("reliost-test-symbol-file-fetch");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let (address, _join_handle) = spawn_app_with_settings(|settings| {
+        let breakpad = settings
+            .symbols
+            .as_mut()
+            .unwrap()
+            .breakpad
+            .as_mut()
+            .unwrap();
+        breakpad.servers = vec![server.symbol_server()];
+        breakpad.cache_dir = cache_dir;
+    });
+
+    let url = format!("http://{address}/symbol_file_fetch.pdb/{DEBUG_ID}/symbol_file_fetch.sym");
+    let client = reqwest::Client::new();
+    let response = client
+        .head(&url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(server.requested_paths().is_empty());
+
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.bytes().await.unwrap(), contents.as_bytes());
+    assert_eq!(server.requested_paths().len(), 1);
+
+    let response = client
+        .head(&url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(server.requested_paths().len(), 1);
+}
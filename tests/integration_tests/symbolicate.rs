@@ -0,0 +1,343 @@
+use std::time::Duration;
+
+use crate::helpers::{
+    spawn_app, spawn_app_with_settings, StubResponse, StubServer, ADMIN_TOKEN,
+    MAX_REQUEST_BODY_SIZE,
+};
+
+async fn post_symbolicate_v5(address: &str, body: Vec<u8>) -> (u16, serde_json::Value) {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{address}/symbolicate/v5"))
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let status = response.status().as_u16();
+    let body = response
+        .bytes()
+        .await
+        .expect("Failed to read response body.");
+    let body = serde_json::from_slice(&body).expect("Expected a JSON body.");
+    (status, body)
+}
+
+#[tokio::test]
+async fn symbolicate_v5_rejects_invalid_json() {
+    let (address, _join_handle) = spawn_app();
+
+    let (status, body) = post_symbolicate_v5(&address, b"{ \"jobs\": [".to_vec()).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "invalid_request");
+}
+
+#[tokio::test]
+async fn symbolicate_v5_names_the_invalid_field() {
+    let (address, _join_handle) = spawn_app();
+
+    let body = br#"{ "memoryMap": [["xul.pdb", "44E4EC8C2F41492B9369D6B9A059577C2"]] }"#;
+    let (status, body) = post_symbolicate_v5(&address, body.to_vec()).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "invalid_request");
+    let message = body["error"]["message"].as_str().unwrap();
+    assert!(message.contains("missing field `stacks`"), "{message}");
+
+    let body =
+        br#"{ "jobs": [{ "memoryMap": [["xul.pdb", "not-a-breakpad-id"]], "stacks": [] }] }"#;
+    let (status, body) = post_symbolicate_v5(&address, body.to_vec()).await;
+    assert_eq!(status, 400);
+    let message = body["error"]["message"].as_str().unwrap();
+    assert!(message.contains("Invalid breakpad ID"), "{message}");
+}
+
+#[tokio::test]
+async fn symbolicate_v5_rejects_oversized_body() {
+    let (address, _join_handle) = spawn_app();
+
+    // A syntactically valid prefix followed by whitespace, so that the parser
+    // keeps reading until the limit is hit.
+    let mut body = b"{ \"jobs\": [".to_vec();
+    body.resize(MAX_REQUEST_BODY_SIZE as usize + 1, b' ');
+    let (status, body) = post_symbolicate_v5(&address, body).await;
+    assert_eq!(status, 413);
+    assert_eq!(body["error"]["code"], "payload_too_large");
+}
+
+#[tokio::test]
+async fn asm_v1_rejects_invalid_utf8() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{address}/asm/v1"))
+        .body(vec![b'{', 0xff, 0xfe, b'}'])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body = response
+        .bytes()
+        .await
+        .expect("Failed to read response body.");
+    let body: serde_json::Value = serde_json::from_slice(&body).expect("Expected a JSON body.");
+    assert_eq!(body["error"]["code"], "invalid_utf8");
+}
+
+#[tokio::test]
+async fn symbolicate_v5_limits_decompressed_size() {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let (address, _join_handle) = spawn_app();
+
+    // This compresses to a few kilobytes, but decompresses to more than the
+    // limit.
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(b"{ \"jobs\": [").unwrap();
+    encoder
+        .write_all(&vec![b' '; 2 * MAX_REQUEST_BODY_SIZE as usize])
+        .unwrap();
+    let compressed = encoder.finish().unwrap();
+    assert!(compressed.len() < MAX_REQUEST_BODY_SIZE as usize);
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{address}/symbolicate/v5"))
+        .header("Content-Encoding", "gzip")
+        .body(compressed)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 413);
+}
+
+#[tokio::test]
+async fn symbolicate_v5_rejects_unsupported_content_encoding() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{address}/symbolicate/v5"))
+        .header("Content-Encoding", "compress")
+        .body("{}")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 415);
+}
+
+const SINGLE_FRAME_REQUEST: &str = r#"{
+    "jobs": [{
+        "memoryMap": [["xul.pdb", "44E4EC8C2F41492B9369D6B9A059577C2"]],
+        "stacks": [[[0, 4096]]]
+    }]
+}"#;
+
+#[tokio::test]
+async fn symbolicate_v5_responds_uncompressed_without_accept_encoding() {
+    let (address, _join_handle) = spawn_app();
+
+    let (status, body) =
+        post_symbolicate_v5(&address, SINGLE_FRAME_REQUEST.as_bytes().to_vec()).await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        body["results"][0]["stacks"][0][0]["module_offset"],
+        "0x1000"
+    );
+}
+
+#[tokio::test]
+async fn symbolicate_v5_negotiates_zstd() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{address}/symbolicate/v5"))
+        .header("Accept-Encoding", "gzip;q=0.5, zstd")
+        .body(SINGLE_FRAME_REQUEST)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("Content-Encoding").unwrap(), "zstd");
+
+    let body = response
+        .bytes()
+        .await
+        .expect("Failed to read response body.");
+    let body = zstd::decode_all(&body[..]).expect("Expected a zstd body.");
+    let body: serde_json::Value = serde_json::from_slice(&body).expect("Expected a JSON body.");
+    assert_eq!(body["results"][0]["stacks"][0][0]["module"], "xul.pdb");
+}
+
+#[tokio::test]
+async fn symbolicate_v5_rejects_invalid_deadline_header() {
+    let (address, _join_handle) = spawn_app();
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("http://{address}/symbolicate/v5"))
+        .header("X-Symbolication-Deadline", "soon")
+        .body(SINGLE_FRAME_REQUEST)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn symbolicate_v5_skips_unreferenced_modules() {
+    let (address, _join_handle) = spawn_app();
+
+    // No frame refers to ntdll.pdb, so it isn't looked up.
+    let request = r#"{
+        "jobs": [{
+            "memoryMap": [
+                ["xul.pdb", "44E4EC8C2F41492B9369D6B9A059577C2"],
+                ["ntdll.pdb", "08A413EE85E91D0377BA33DC3A2641941"]
+            ],
+            "stacks": [[[0, 4096]]]
+        }]
+    }"#;
+    let (status, body) = post_symbolicate_v5(&address, request.as_bytes().to_vec()).await;
+    assert_eq!(status, 200);
+    let found_modules = &body["results"][0]["found_modules"];
+    assert!(found_modules["xul.pdb/44E4EC8C2F41492B9369D6B9A059577C2"].is_boolean());
+    assert!(found_modules["ntdll.pdb/08A413EE85E91D0377BA33DC3A2641941"].is_null());
+    assert!(found_modules
+        .as_object()
+        .unwrap()
+        .contains_key("ntdll.pdb/08A413EE85E91D0377BA33DC3A2641941"));
+    assert!(body["results"][0]["module_errors"]
+        .get("ntdll.pdb/08A413EE85E91D0377BA33DC3A2641941")
+        .is_none());
+}
+
+#[tokio::test]
+async fn symbolicate_v5_cancels_downloads_when_the_client_disconnects() {
+    let server = StubServer::spawn(|_| StubResponse::Stall);
+    let cache_dir = std::env::temp_dir().join("reliost-test-cancel-on-disconnect");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let (address, _join_handle) = spawn_app_with_settings(|settings| {
+        let breakpad = settings
+            .symbols
+            .as_mut()
+            .unwrap()
+            .breakpad
+            .as_mut()
+            .unwrap();
+        breakpad.servers = vec![server.symbol_server()];
+        breakpad.cache_dir = cache_dir;
+    });
+
+    // The client gives up while the download is stalled, which closes the
+    // connection.
+    let request = r#"{
+        "jobs": [{
+            "memoryMap": [["cancel_on_disconnect.pdb", "44E4EC8C2F41492B9369D6B9A059577C2"]],
+            "stacks": [[[0, 4096]]]
+        }]
+    }"#;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(1))
+        .build()
+        .unwrap();
+    let result = client
+        .post(format!("http://{address}/symbolicate/v5"))
+        .body(request)
+        .send()
+        .await;
+    assert!(result.is_err(), "Expected the request to time out");
+
+    // The lookup task is aborted, which cancels the download.
+    let path =
+        "/cancel_on_disconnect.pdb/44E4EC8C2F41492B9369D6B9A059577C2/cancel_on_disconnect.sym";
+    for _ in 0..100 {
+        if !server.abandoned_paths().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(server.abandoned_paths(), [path]);
+
+    let response = reqwest::Client::new()
+        .get(format!("http://{address}/admin/downloads"))
+        .bearer_auth(ADMIN_TOKEN)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let body: serde_json::Value =
+        serde_json::from_slice(&response.bytes().await.unwrap()).expect("Expected a JSON body.");
+    assert_eq!(body["inFlight"].as_array().unwrap().len(), 0);
+    let recent = &body["recent"][0];
+    assert!(recent["url"].as_str().unwrap().ends_with(path));
+    assert_eq!(recent["outcome"], "canceled");
+}
+
+#[tokio::test]
+async fn symbolicate_v5_returns_partial_results_at_the_deadline() {
+    let server = StubServer::spawn(|_| StubResponse::Stall);
+    let symbol_dir = std::env::temp_dir().join("reliost-test-partial-results");
+    let sym_dir = symbol_dir.join("partial_found.pdb/44E4EC8C2F41492B9369D6B9A059577C2");
+    std::fs::create_dir_all(&sym_dir).unwrap();
+    std::fs::write(
+        sym_dir.join("partial_found.sym"),
+        "MODULE windows x86_64 44E4EC8C2F41492B9369D6B9A059577C2 partial_found.pdb\n\
+         FUNC 1000 100 0 found_function\n",
+    )
+    .unwrap();
+    let cache_dir = std::env::temp_dir().join("reliost-test-partial-results-cache");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let (address, _join_handle) = spawn_app_with_settings(|settings| {
+        let breakpad = settings
+            .symbols
+            .as_mut()
+            .unwrap()
+            .breakpad
+            .as_mut()
+            .unwrap();
+        breakpad.dirs = vec![symbol_dir];
+        breakpad.servers = vec![server.symbol_server()];
+        breakpad.cache_dir = cache_dir;
+    });
+
+    // The symbols of partial_stalled.pdb are never downloaded completely.
+    let request = r#"{
+        "jobs": [{
+            "memoryMap": [
+                ["partial_found.pdb", "44E4EC8C2F41492B9369D6B9A059577C2"],
+                ["partial_stalled.pdb", "44E4EC8C2F41492B9369D6B9A059577C2"]
+            ],
+            "stacks": [[[0, 4112], [1, 4112]]]
+        }]
+    }"#;
+    let started_at = std::time::Instant::now();
+    let response = reqwest::Client::new()
+        .post(format!("http://{address}/symbolicate/v5"))
+        .header("X-Symbolication-Deadline", "1s")
+        .body(request)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(started_at.elapsed() < Duration::from_secs(10));
+    let body: serde_json::Value =
+        serde_json::from_slice(&response.bytes().await.unwrap()).expect("Expected a JSON body.");
+
+    let result = &body["results"][0];
+    assert_eq!(
+        result["found_modules"]["partial_found.pdb/44E4EC8C2F41492B9369D6B9A059577C2"],
+        true
+    );
+    assert_eq!(result["stacks"][0][0]["function"], "found_function");
+    assert_eq!(
+        result["found_modules"]["partial_stalled.pdb/44E4EC8C2F41492B9369D6B9A059577C2"],
+        false
+    );
+    assert_eq!(
+        result["module_errors"]["partial_stalled.pdb/44E4EC8C2F41492B9369D6B9A059577C2"][0]["name"],
+        "Timeout"
+    );
+    assert!(result["stacks"][0][1].get("function").is_none());
+}
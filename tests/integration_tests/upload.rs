@@ -0,0 +1,127 @@
+use crate::helpers::{spawn_app, upload_dir, UPLOAD_TOKEN};
+
+async fn post_upload(
+    address: &str,
+    token: Option<&str>,
+    body: Vec<u8>,
+) -> (u16, serde_json::Value) {
+    let client = reqwest::Client::new();
+    let mut request = client.post(format!("http://{address}/upload")).body(body);
+    if let Some(token) = token {
+        request = request.header("Auth-Token", token);
+    }
+    let response = request.send().await.expect("Failed to execute request.");
+    let status = response.status().as_u16();
+    let body = response
+        .bytes()
+        .await
+        .expect("Failed to read response body.");
+    let body = serde_json::from_slice(&body).expect("Expected a JSON body.");
+    (status, body)
+}
+
+fn sym_file(debug_name: &str, breakpad_id: &str) -> Vec<u8> {
+    format!("MODULE windows x86_64 {breakpad_id} {debug_name}\nFUNC 1000 10 0 main\n").into_bytes()
+}
+
+#[tokio::test]
+async fn upload_requires_a_token() {
+    let (address, _join_handle) = spawn_app();
+
+    let body = sym_file(
+        "upload_requires_a_token.pdb",
+        "44E4EC8C2F41492B9369D6B9A059577C2",
+    );
+    let (status, body) = post_upload(&address, None, body).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"]["code"], "unauthorized");
+}
+
+#[tokio::test]
+async fn upload_rejects_invalid_tokens() {
+    let (address, _join_handle) = spawn_app();
+
+    let body = sym_file(
+        "upload_rejects_invalid_tokens.pdb",
+        "44E4EC8C2F41492B9369D6B9A059577C2",
+    );
+    let (status, body) = post_upload(&address, Some("wrong"), body).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"]["code"], "forbidden");
+}
+
+#[tokio::test]
+async fn upload_stores_sym_files() {
+    let (address, _join_handle) = spawn_app();
+
+    let contents = sym_file(
+        "upload_stores_sym_files.pdb",
+        "44E4EC8C2F41492B9369D6B9A059577C2",
+    );
+    let (status, body) = post_upload(&address, Some(UPLOAD_TOKEN), contents.clone()).await;
+    assert_eq!(status, 201);
+    let path =
+        "upload_stores_sym_files.pdb/44E4EC8C2F41492B9369D6B9A059577C2/upload_stores_sym_files.sym";
+    assert_eq!(body["files"], serde_json::json!([path]));
+    assert_eq!(std::fs::read(upload_dir().join(path)).unwrap(), contents);
+}
+
+#[tokio::test]
+async fn upload_stores_tar_archives() {
+    let (address, _join_handle) = spawn_app();
+
+    let contents = sym_file(
+        "upload_stores_tar_archives.so",
+        "83CA53B0E8272691CEFCD79178D33D5C0",
+    );
+    let path = "upload_stores_tar_archives.so/83CA53B0E8272691CEFCD79178D33D5C0/upload_stores_tar_archives.so.sym";
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, path, contents.as_slice())
+        .unwrap();
+    let archive = builder.into_inner().unwrap();
+
+    let (status, body) = post_upload(&address, Some(UPLOAD_TOKEN), archive).await;
+    assert_eq!(status, 201);
+    assert_eq!(body["files"], serde_json::json!([path]));
+    assert_eq!(std::fs::read(upload_dir().join(path)).unwrap(), contents);
+}
+
+#[tokio::test]
+async fn upload_rejects_mismatched_archive_paths() {
+    let (address, _join_handle) = spawn_app();
+
+    let contents = sym_file(
+        "upload_rejects_mismatched.so",
+        "83CA53B0E8272691CEFCD79178D33D5C0",
+    );
+    let path = "other.so/83CA53B0E8272691CEFCD79178D33D5C0/other.so.sym";
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, path, contents.as_slice())
+        .unwrap();
+    let archive = builder.into_inner().unwrap();
+
+    let (status, body) = post_upload(&address, Some(UPLOAD_TOKEN), archive).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "invalid_upload");
+    assert!(!upload_dir().join(path).exists());
+}
+
+#[tokio::test]
+async fn upload_rejects_other_files() {
+    let (address, _join_handle) = spawn_app();
+
+    let (status, body) =
+        post_upload(&address, Some(UPLOAD_TOKEN), b"not a symbol file".to_vec()).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "invalid_upload");
+}